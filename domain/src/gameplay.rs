@@ -0,0 +1,60 @@
+use bevy::prelude::{
+    Component, Dir3, GlobalTransform, Query, ReflectComponent, ReflectDefault, Res, SystemSet,
+    Time, Transform, Vec3,
+};
+use bevy::reflect::Reflect;
+use crate::guid::{EntityLink, LinksEntities};
+
+/// Systems that simulate the game. A runtime runs them every frame; the SDK only while playing.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameplaySet;
+
+/// Rotates the entity around `axis` at `speed` radians per second.
+#[derive(Component, Reflect, Clone, Debug, PartialEq)]
+#[reflect(Component, Default)]
+#[require(Transform)]
+pub struct Spin {
+    pub axis: Vec3,
+    pub speed: f32,
+}
+
+impl Default for Spin {
+    fn default() -> Self {
+        Self {
+            axis: Vec3::Y,
+            speed: 1.0,
+        }
+    }
+}
+
+pub fn spin(time: Res<Time>, mut query: Query<(&Spin, &mut Transform)>) {
+    for (spin, mut transform) in query.iter_mut() {
+        let Ok(axis) = Dir3::new(spin.axis) else {
+            continue;
+        };
+        transform.rotate_axis(axis, spin.speed * time.delta_secs());
+    }
+}
+
+/// Turns the entity to face another one.
+#[derive(Component, Reflect, Clone, Debug, Default, PartialEq)]
+#[reflect(Component, Default)]
+#[require(Transform)]
+pub struct LookAt {
+    pub target: EntityLink,
+}
+
+impl LinksEntities for LookAt {
+    fn entity_links(&mut self) -> Vec<&mut EntityLink> {
+        vec![&mut self.target]
+    }
+}
+
+pub fn look_at(mut query: Query<(&LookAt, &mut Transform)>, targets: Query<&GlobalTransform>) {
+    for (look_at, mut transform) in query.iter_mut() {
+        let Some(target) = look_at.target.entity.and_then(|entity| targets.get(entity).ok()) else {
+            continue;
+        };
+        transform.look_at(target.translation(), Vec3::Y);
+    }
+}
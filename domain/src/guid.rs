@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use bevy::prelude::{Component, DetectChangesMut, Entity, Query, ReflectComponent, ReflectDefault};
+use bevy::reflect::Reflect;
+use uuid::Uuid;
+
+/// Identity of a scene entity that survives saving and loading, unlike its `Entity` id.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct SceneGuid(pub Uuid);
+
+impl SceneGuid {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for SceneGuid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reference from a domain component to another scene entity.
+///
+/// Only the [`SceneGuid`] is saved; the entity is looked up by [`resolve_entity_links`]
+/// once the target exists in the world.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq)]
+#[reflect(Default)]
+pub struct EntityLink {
+    pub guid: Option<Uuid>,
+    #[reflect(ignore)]
+    pub entity: Option<Entity>,
+}
+
+impl EntityLink {
+    pub fn to(guid: SceneGuid) -> Self {
+        Self {
+            guid: Some(guid.0),
+            entity: None,
+        }
+    }
+}
+
+/// Components with [`EntityLink`] fields.
+pub trait LinksEntities: Component {
+    fn entity_links(&mut self) -> Vec<&mut EntityLink>;
+}
+
+/// Points the links of `T` at the entities carrying their GUIDs.
+pub fn resolve_entity_links<T: LinksEntities>(
+    mut components: Query<&mut T>,
+    guids: Query<(Entity, &SceneGuid)>,
+) {
+    let is_resolved = |link: &EntityLink| match (link.guid, link.entity) {
+        (None, None) => true,
+        (Some(guid), Some(entity)) => guids.get(entity).is_ok_and(|(_, target)| target.0 == guid),
+        _ => false,
+    };
+
+    let mut by_guid: Option<HashMap<Uuid, Entity>> = None;
+    for mut component in components.iter_mut() {
+        // The cached entity isn't part of the reflected value, so finding it isn't an edit.
+        for link in component.bypass_change_detection().entity_links() {
+            if is_resolved(link) {
+                continue;
+            }
+            let by_guid = by_guid.get_or_insert_with(|| {
+                guids.iter().map(|(entity, guid)| (guid.0, entity)).collect()
+            });
+            link.entity = link.guid.and_then(|guid| by_guid.get(&guid).copied());
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use bevy::log::error;
+use bevy::pbr::{CascadeShadowConfig, Cascades, CascadesVisibleEntities, CubemapVisibleEntities};
+use bevy::prelude::{
+    AmbientLight, DynamicScene, DynamicSceneBuilder, Entity, GlobalTransform, InheritedVisibility,
+    Mesh3d, MeshMaterial3d, ReflectDefault, ReflectResource, Res, Resource, StandardMaterial,
+    Transform, ViewVisibility, World,
+};
+use bevy::reflect::Reflect;
+use bevy::render::primitives::{Aabb, CascadesFrusta, CubemapFrusta};
+use bevy::render::sync_world::SyncToRenderWorld;
+
+/// Format version written into every level. Bump it when a change to the domain types
+/// makes older levels load differently.
+pub const LEVEL_VERSION: u32 = 1;
+
+/// Level-wide data, saved with the level as a resource.
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource, Default)]
+pub struct LevelInfo {
+    pub name: String,
+    /// [`LEVEL_VERSION`] of the build that saved the level.
+    pub version: u32,
+    /// Where the editor camera starts when the level is opened, `None` to leave it where it is.
+    #[reflect(default)]
+    pub camera_start: Option<Transform>,
+}
+
+impl Default for LevelInfo {
+    fn default() -> Self {
+        Self {
+            name: "Untitled".to_string(),
+            version: LEVEL_VERSION,
+            camera_start: None,
+        }
+    }
+}
+
+/// Scene builder that leaves out components which are derived every frame or hold asset
+/// handles; `SceneMesh` and `SceneMaterial` bring the meshes back on load.
+///
+/// The components lights require are left out too: they come back with their defaults when the
+/// light is inserted, and a headless player doesn't register them.
+pub fn level_scene_builder(world: &World) -> DynamicSceneBuilder<'_> {
+    DynamicSceneBuilder::from_world(world)
+        .deny_component::<GlobalTransform>()
+        .deny_component::<InheritedVisibility>()
+        .deny_component::<ViewVisibility>()
+        .deny_component::<Aabb>()
+        .deny_component::<CubemapFrusta>()
+        .deny_component::<CubemapVisibleEntities>()
+        .deny_component::<Cascades>()
+        .deny_component::<CascadesFrusta>()
+        .deny_component::<CascadeShadowConfig>()
+        .deny_component::<CascadesVisibleEntities>()
+        .deny_component::<SyncToRenderWorld>()
+        .deny_component::<Mesh3d>()
+        .deny_component::<MeshMaterial3d<StandardMaterial>>()
+}
+
+/// Builds the saved form of a level from `entities`, see [`level_scene_builder`].
+pub fn extract_level(world: &World, entities: impl Iterator<Item = Entity>) -> DynamicScene {
+    level_scene_builder(world)
+        .allow_resource::<LevelInfo>()
+        .allow_resource::<AmbientLight>()
+        .extract_entities(entities)
+        .extract_resources()
+        .build()
+}
+
+/// Reports levels saved by a newer build, which may contain data this one doesn't understand.
+pub fn check_level_version(level: Res<LevelInfo>) {
+    if level.version > LEVEL_VERSION {
+        error!(
+            "Level \"{}\" has format version {}, but this build only supports up to {LEVEL_VERSION}",
+            level.name, level.version
+        );
+    }
+}
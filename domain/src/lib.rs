@@ -0,0 +1,52 @@
+//! Game-facing data model shared by the SDK and the runtime, so the editor saves exactly
+//! what the game loads.
+
+use bevy::prelude::{
+    resource_changed, resource_exists, App, Assets, Condition, IntoSystemConfigs, Mesh, Plugin,
+    StandardMaterial, Update,
+};
+
+mod gameplay;
+mod guid;
+mod level;
+mod mesh;
+mod prefab;
+
+pub use gameplay::{look_at, spin, GameplaySet, LookAt, Spin};
+pub use guid::{resolve_entity_links, EntityLink, LinksEntities, SceneGuid};
+pub use level::{
+    check_level_version, extract_level, level_scene_builder, LevelInfo, LEVEL_VERSION,
+};
+pub use mesh::{build_scene_meshes, SceneMaterial, SceneMesh};
+pub use prefab::{PrefabInstance, PrefabOverride};
+
+/// Registers the domain types for reflection and scenes, keeps their render data in sync
+/// and adds the gameplay systems to [`GameplaySet`].
+pub struct DomainPlugin;
+
+impl Plugin for DomainPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SceneMesh>()
+            .register_type::<SceneMaterial>()
+            .register_type::<SceneGuid>()
+            .register_type::<EntityLink>()
+            .register_type::<Spin>()
+            .register_type::<LookAt>()
+            .register_type::<PrefabInstance>()
+            .register_type::<LevelInfo>()
+            .init_resource::<LevelInfo>()
+            .add_systems(
+                Update,
+                (
+                    // Headless runtimes have no render assets to build.
+                    build_scene_meshes.run_if(
+                        resource_exists::<Assets<Mesh>>
+                            .and(resource_exists::<Assets<StandardMaterial>>),
+                    ),
+                    check_level_version.run_if(resource_changed::<LevelInfo>),
+                ),
+            )
+            .add_systems(Update, resolve_entity_links::<LookAt>.before(GameplaySet))
+            .add_systems(Update, (spin, look_at).in_set(GameplaySet));
+    }
+}
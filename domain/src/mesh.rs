@@ -0,0 +1,66 @@
+use bevy::prelude::{
+    Assets, Changed, Color, Commands, Component, Cuboid, Entity, LinearRgba, Mesh, Mesh3d,
+    MeshMaterial3d, Meshable, Plane3d, Query, ReflectComponent, ResMut, Sphere, StandardMaterial,
+    Transform, Vec2, Vec3, Visibility,
+};
+use bevy::reflect::Reflect;
+
+/// Shape of a scene entity's mesh.
+///
+/// Scenes can't store `Mesh3d` handles, so content describes its mesh with this
+/// and [`build_scene_meshes`] creates the actual asset.
+#[derive(Component, Reflect, Clone, Debug, PartialEq)]
+#[reflect(Component)]
+#[require(Transform, Visibility)]
+pub enum SceneMesh {
+    Cuboid(Vec3),
+    Plane(Vec2),
+    Sphere(f32),
+}
+
+/// Serializable counterpart of `MeshMaterial3d<StandardMaterial>`, see [`SceneMesh`].
+#[derive(Component, Reflect, Clone, Debug, PartialEq)]
+#[reflect(Component)]
+pub struct SceneMaterial {
+    pub base_color: Color,
+    pub emissive: LinearRgba,
+}
+
+impl SceneMaterial {
+    pub fn new(base_color: Color) -> Self {
+        Self {
+            base_color,
+            emissive: LinearRgba::BLACK,
+        }
+    }
+}
+
+impl From<&SceneMesh> for Mesh {
+    fn from(mesh: &SceneMesh) -> Self {
+        match *mesh {
+            SceneMesh::Cuboid(size) => Cuboid::from_size(size).into(),
+            SceneMesh::Plane(size) => Plane3d::default().mesh().size(size.x, size.y).into(),
+            SceneMesh::Sphere(radius) => Sphere::new(radius).into(),
+        }
+    }
+}
+
+/// Creates render assets for new or edited [`SceneMesh`] and [`SceneMaterial`] components.
+pub fn build_scene_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    changed_meshes: Query<(Entity, &SceneMesh), Changed<SceneMesh>>,
+    changed_materials: Query<(Entity, &SceneMaterial), Changed<SceneMaterial>>,
+) {
+    for (entity, mesh) in changed_meshes.iter() {
+        commands.entity(entity).insert(Mesh3d(meshes.add(Mesh::from(mesh))));
+    }
+    for (entity, material) in changed_materials.iter() {
+        commands.entity(entity).insert(MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: material.base_color,
+            emissive: material.emissive,
+            ..Default::default()
+        })));
+    }
+}
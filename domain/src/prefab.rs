@@ -0,0 +1,24 @@
+use bevy::prelude::{Component, ReflectComponent};
+use bevy::reflect::Reflect;
+
+/// Marks an entity as part of a prefab instance, placed with the SDK.
+///
+/// Saved levels contain the full data of every instance, so a runtime can ignore this.
+#[derive(Component, Reflect, Clone, Debug, Default, PartialEq)]
+#[reflect(Component)]
+pub struct PrefabInstance {
+    /// Asset path of the prefab.
+    pub prefab: String,
+    /// Bits of the corresponding entity id inside the prefab file.
+    pub source: u64,
+    /// Fields that differ from the prefab and are kept when the prefab changes.
+    pub overrides: Vec<PrefabOverride>,
+}
+
+/// A reflected field of a component, e.g. `.translation` of `Transform`.
+#[derive(Reflect, Clone, Debug, Default, PartialEq)]
+pub struct PrefabOverride {
+    pub component: String,
+    /// Reflection path into the component; empty when the whole component is overridden.
+    pub path: String,
+}
@@ -0,0 +1,249 @@
+use std::path::PathBuf;
+use bevy::asset::LoadState;
+use bevy::core::FrameCount;
+use bevy::hierarchy::HierarchyPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::scene::{SceneInstanceReady, ScenePlugin};
+use bevy::transform::TransformPlugin;
+use domain::DomainPlugin;
+
+const USAGE: &str = "usage: player <scene.scn.ron> [--headless] [--frames N]";
+
+/// Command line of the player. The scene path is relative to the assets folder, or absolute.
+struct Args {
+    scene: PathBuf,
+    /// Run without a window or renderer, for automated smoke tests.
+    headless: bool,
+    /// Exit after this many frames, with an error if the scene hasn't loaded by then.
+    frames: Option<u32>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut scene = None;
+        let mut headless = false;
+        let mut frames = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--frames" => {
+                    let value = args.next().ok_or("--frames needs a value")?;
+                    let value = value
+                        .parse()
+                        .map_err(|_| format!("invalid frame count: {value}"))?;
+                    frames = Some(value);
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+                _ if scene.is_none() => scene = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument: {arg}")),
+            }
+        }
+
+        Ok(Self {
+            scene: scene.ok_or("missing scene path")?,
+            headless,
+            frames,
+        })
+    }
+}
+
+#[derive(Resource)]
+struct SceneToLoad(PathBuf);
+
+#[derive(Resource)]
+struct Level {
+    scene: Handle<DynamicScene>,
+    loaded: bool,
+}
+
+#[derive(Resource)]
+struct FrameLimit(u32);
+
+fn main() -> AppExit {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}\n{USAGE}");
+            return AppExit::error();
+        }
+    };
+    app(args).run()
+}
+
+fn app(args: Args) -> App {
+    let mut app = App::new();
+    if args.headless {
+        app.add_plugins((
+            MinimalPlugins,
+            LogPlugin::default(),
+            AssetPlugin::default(),
+            ScenePlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+        ))
+        // Registered by the render plugins in a full build; scenes reference them.
+        .register_type::<Visibility>()
+        .register_type::<PointLight>()
+        .register_type::<DirectionalLight>()
+        .register_type::<AmbientLight>();
+    } else {
+        app.add_plugins(DefaultPlugins)
+            .add_systems(Startup, spawn_camera);
+    }
+
+    if let Some(frames) = args.frames {
+        app.insert_resource(FrameLimit(frames))
+            .add_systems(Last, exit_after_frames);
+    }
+
+    app.add_plugins(DomainPlugin)
+        .insert_resource(SceneToLoad(args.scene))
+        .add_systems(Startup, load_level)
+        .add_systems(Update, exit_on_load_failure);
+    app
+}
+
+fn load_level(mut commands: Commands, asset_server: Res<AssetServer>, path: Res<SceneToLoad>) {
+    let scene = asset_server.load(path.0.clone());
+    commands
+        .spawn(DynamicSceneRoot(scene.clone()))
+        .observe(level_loaded);
+    commands.insert_resource(Level {
+        scene,
+        loaded: false,
+    });
+}
+
+fn level_loaded(
+    trigger: Trigger<SceneInstanceReady>,
+    children: Query<&Children>,
+    mut level: ResMut<Level>,
+) {
+    let entities = children.iter_descendants(trigger.entity()).count();
+    info!("Loaded level with {entities} entities");
+    level.loaded = true;
+}
+
+/// Scenes from the SDK contain no camera, the editor camera isn't part of them.
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 1.0, 4.0).looking_at(Vec3::Y, Vec3::Y),
+    ));
+}
+
+fn exit_on_load_failure(
+    asset_server: Res<AssetServer>,
+    level: Res<Level>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if let Some(LoadState::Failed(error)) = asset_server.get_load_state(&level.scene) {
+        error!("Failed to load level: {error}");
+        exit.send(AppExit::error());
+    }
+}
+
+fn exit_after_frames(
+    frame_limit: Res<FrameLimit>,
+    frame_count: Res<FrameCount>,
+    level: Res<Level>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if frame_count.0 < frame_limit.0 {
+        return;
+    }
+    if level.loaded {
+        info!("Ran {} frames", frame_limit.0);
+        exit.send(AppExit::Success);
+    } else {
+        error!("Level didn't finish loading within {} frames", frame_limit.0);
+        exit.send(AppExit::error());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use domain::{extract_level, LevelInfo};
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options_around_the_scene() {
+        let args = parse(&["--headless", "level.scn.ron", "--frames", "10"]).unwrap();
+        assert_eq!(args.scene, PathBuf::from("level.scn.ron"));
+        assert!(args.headless);
+        assert_eq!(args.frames, Some(10));
+
+        let args = parse(&["level.scn.ron"]).unwrap();
+        assert!(!args.headless);
+        assert_eq!(args.frames, None);
+    }
+
+    #[test]
+    fn rejects_bad_command_lines() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--headless"]).is_err());
+        assert!(parse(&["level.scn.ron", "--frames"]).is_err());
+        assert!(parse(&["level.scn.ron", "--frames", "ten"]).is_err());
+        assert!(parse(&["level.scn.ron", "--fast"]).is_err());
+        assert!(parse(&["level.scn.ron", "other.scn.ron"]).is_err());
+    }
+
+    fn headless(scene: PathBuf) -> App {
+        app(Args {
+            scene,
+            headless: true,
+            frames: None,
+        })
+    }
+
+    /// Updates until the level is loaded or it gives up.
+    fn update_until_loaded(app: &mut App) {
+        for _ in 0..500 {
+            app.update();
+            if app.world().get_resource::<Level>().is_some_and(|level| level.loaded) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn loads_a_directional_light_headless() {
+        let path = std::env::temp_dir().join(format!("player-light-{}.scn.ron", std::process::id()));
+        let mut app = headless(path.clone());
+
+        // Saved the way the SDK saves levels, from a world with the player's registrations.
+        let world = app.world_mut();
+        let light = world.spawn(DirectionalLight::default()).id();
+        let scene = extract_level(world, [light].into_iter());
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        std::fs::write(&path, scene.serialize(&type_registry.read()).unwrap()).unwrap();
+        world.despawn(light);
+
+        update_until_loaded(&mut app);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(app.world().resource::<Level>().loaded);
+        let world = app.world_mut();
+        assert_eq!(world.query::<&DirectionalLight>().iter(world).count(), 1);
+    }
+
+    #[test]
+    fn loads_the_sdk_templates_headless() {
+        let templates = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../sdk/assets/templates");
+        for template in ["empty", "cornell_box", "outdoor"] {
+            let mut app = headless(templates.join(format!("{template}.scn.ron")));
+            update_until_loaded(&mut app);
+            assert!(app.world().resource::<Level>().loaded, "{template} didn't load");
+            let camera_start = app.world().resource::<LevelInfo>().camera_start;
+            assert_eq!(camera_start.is_some(), template != "empty", "{template}");
+        }
+    }
+}
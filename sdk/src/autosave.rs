@@ -0,0 +1,301 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::log::{info, warn};
+use bevy::prelude::{AppTypeRegistry, Resource, Time, Timer, TimerMode, World};
+use bevy::tasks::{block_on, IoTaskPool, Task};
+use egui_dock::DockState;
+use serde::{Deserialize, Serialize};
+use crate::command_record::{history_path, HistoryRecord};
+use crate::editor_commands::HistoryManager;
+use crate::scene_file::{
+    clear_scene, deserialize_scene, file_path, history_record, restore_history, serialize_scene,
+    CurrentScene,
+};
+use crate::{EguiWindow, UiState};
+
+/// Where unsaved work is written, relative to the working directory.
+pub const RECOVERY_DIR: &str = "recovery";
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+const SCENE_FILE: &str = "scene.scn.ron";
+const LAYOUT_FILE: &str = "layout.ron";
+const INFO_FILE: &str = "recovery.ron";
+
+fn recovery_path(file: &str) -> PathBuf {
+    Path::new(RECOVERY_DIR).join(file)
+}
+
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Which scene the files in [`RECOVERY_DIR`] are unsaved work on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Recovery {
+    /// Asset path of the scene, `None` if it was never saved.
+    pub scene: Option<PathBuf>,
+    /// [`CurrentScene::generation`] the unsaved work is based on.
+    #[serde(default)]
+    pub generation: u64,
+}
+
+#[derive(Resource)]
+pub struct Autosave {
+    timer: Timer,
+    /// The recovery write in flight on the IO task pool.
+    task: Option<Task<()>>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            timer: Timer::new(AUTOSAVE_INTERVAL, TimerMode::Repeating),
+            task: None,
+        }
+    }
+}
+
+impl Autosave {
+    /// Waits for the recovery write in flight, if any, so it can't land after the files are replaced or deleted.
+    pub fn finish(&mut self) {
+        if let Some(task) = self.task.take() {
+            block_on(task);
+        }
+    }
+}
+
+/// Everything written to [`RECOVERY_DIR`], collected from the world so it can be written elsewhere.
+struct RecoveryFiles {
+    scene: String,
+    history: HistoryRecord,
+    layout: String,
+    info: String,
+}
+
+impl RecoveryFiles {
+    fn collect(world: &mut World, layout: &DockState<EguiWindow>) -> io::Result<Self> {
+        let current_scene = world.resource::<CurrentScene>();
+        let info = Recovery {
+            scene: current_scene.path.clone(),
+            generation: current_scene.generation,
+        };
+        Ok(Self {
+            scene: serialize_scene(world)?,
+            history: history_record(world),
+            layout: ron::to_string(layout).map_err(invalid_data)?,
+            info: ron::to_string(&info).map_err(invalid_data)?,
+        })
+    }
+
+    fn write(self) -> io::Result<()> {
+        std::fs::create_dir_all(RECOVERY_DIR)?;
+        // The info goes last, `find_recovery` only offers files it describes.
+        self.history.save(&recovery_path(SCENE_FILE))?;
+        std::fs::write(recovery_path(LAYOUT_FILE), self.layout)?;
+        std::fs::write(recovery_path(SCENE_FILE), self.scene)?;
+        std::fs::write(recovery_path(INFO_FILE), self.info)
+    }
+}
+
+/// Periodically writes unsaved work to [`RECOVERY_DIR`]. The files are written on the IO task pool.
+pub fn autosave(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    if !world.resource_mut::<Autosave>().timer.tick(delta).just_finished() {
+        return;
+    }
+    if !world.resource::<HistoryManager>().is_dirty() {
+        return;
+    }
+
+    // One write at a time, so an older one can't finish after a newer one.
+    world.resource_mut::<Autosave>().finish();
+    let files = world.resource_scope::<UiState, _>(|world, ui_state| {
+        RecoveryFiles::collect(world, &ui_state.state)
+    });
+    match files {
+        Ok(files) => {
+            let task = IoTaskPool::get().spawn(async move {
+                if let Err(error) = files.write() {
+                    warn!("Failed to autosave to {RECOVERY_DIR}: {error}");
+                }
+            });
+            world.resource_mut::<Autosave>().task = Some(task);
+        }
+        Err(error) => warn!("Failed to autosave: {error}"),
+    }
+}
+
+/// Writes unsaved work right away, e.g. while a panic unwinds out of the UI.
+pub fn write_recovery(world: &mut World, layout: &DockState<EguiWindow>) {
+    if let Some(mut autosave) = world.get_resource_mut::<Autosave>() {
+        autosave.finish();
+    }
+    let result = RecoveryFiles::collect(world, layout).and_then(RecoveryFiles::write);
+    match result {
+        Ok(()) => info!("Wrote unsaved work to {RECOVERY_DIR}"),
+        Err(error) => warn!("Failed to write unsaved work to {RECOVERY_DIR}: {error}"),
+    }
+}
+
+/// The recovery left behind by the last session, unless the scene was saved again after it.
+pub fn find_recovery() -> Option<Recovery> {
+    let contents = std::fs::read_to_string(recovery_path(INFO_FILE)).ok()?;
+    let recovery: Recovery = match ron::from_str(&contents) {
+        Ok(recovery) => recovery,
+        Err(error) => {
+            warn!("Ignoring {}: {error}", recovery_path(INFO_FILE).display());
+            return None;
+        }
+    };
+    let scene_file = recovery.scene.as_deref().map(file_path);
+    is_newer_than_save(&recovery, scene_file.as_deref(), &recovery_path(INFO_FILE)).then_some(recovery)
+}
+
+/// Whether the recovery described by `info_file` holds work that `scene_file`, where its scene
+/// was saved, doesn't. Saves are told apart by the generation in the scene's history; a scene
+/// saved without one is compared by modification time instead.
+fn is_newer_than_save(recovery: &Recovery, scene_file: Option<&Path>, info_file: &Path) -> bool {
+    // Nothing of a scene that was never saved is on disk.
+    let Some(scene_file) = scene_file else {
+        return true;
+    };
+    if let Ok(history) = HistoryRecord::load(scene_file) {
+        return recovery.generation >= history.generation;
+    }
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(scene_file), modified(info_file)) {
+        (Ok(saved), Ok(written)) => written > saved,
+        // The scene file is gone, the recovery is all that's left of it.
+        (Err(_), _) => true,
+        (Ok(_), Err(_)) => false,
+    }
+}
+
+/// Deletes the recovery files, once they have been restored or the user turned them down.
+/// A write still in flight is finished first, so it can't leave the files behind.
+pub fn discard_recovery(autosave: &mut Autosave) {
+    autosave.finish();
+    for file in [SCENE_FILE, LAYOUT_FILE, INFO_FILE] {
+        let _ = std::fs::remove_file(recovery_path(file));
+    }
+    let _ = std::fs::remove_file(history_path(&recovery_path(SCENE_FILE)));
+}
+
+/// Replaces the scene, its history and the dock layout with the recovered ones.
+/// The scene stays unsaved, so saving writes the recovered work to its original file.
+pub fn restore_recovery(world: &mut World, layout: &mut DockState<EguiWindow>, recovery: Recovery) {
+    let scene_path = recovery_path(SCENE_FILE);
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let scene = std::fs::read_to_string(&scene_path)
+        .and_then(|contents| deserialize_scene(&contents, &type_registry.read()));
+    let scene = match scene {
+        Ok(scene) => scene,
+        Err(error) => {
+            warn!("Failed to recover {}: {error}", scene_path.display());
+            return;
+        }
+    };
+
+    clear_scene(world);
+    if let Err(error) = scene.write_to_world(world, &mut EntityHashMap::default()) {
+        warn!("Failed to recover {}: {error}", scene_path.display());
+        return;
+    }
+    restore_history(world, &scene_path);
+    world.resource_mut::<HistoryManager>().mark_unsaved();
+    world.resource_mut::<CurrentScene>().path = recovery.scene;
+
+    match std::fs::read_to_string(recovery_path(LAYOUT_FILE))
+        .map_err(|error| error.to_string())
+        .and_then(|contents| ron::from_str(&contents).map_err(|error| error.to_string()))
+    {
+        Ok(recovered) => *layout = recovered,
+        Err(error) => warn!("Keeping the current layout, the recovered one can't be read: {error}"),
+    }
+    discard_recovery(&mut world.resource_mut::<Autosave>());
+}
+
+pub enum RecoveryChoice {
+    Restore(Recovery),
+    Discard,
+}
+
+/// Modal offering to restore the work found by [`find_recovery`]. `offer` is cleared once the user chooses.
+pub fn recovery_ui(ctx: &egui::Context, offer: &mut Option<Recovery>) -> Option<RecoveryChoice> {
+    let recovery = offer.as_ref()?;
+    let mut restore = false;
+    let mut discard = false;
+
+    egui::Window::new("Recover unsaved work")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            let scene = recovery
+                .scene
+                .as_ref()
+                .map_or("an unsaved scene".to_string(), |path| path.display().to_string());
+            ui.label(format!(
+                "The last session ended with unsaved changes to {scene}. Restore them?"
+            ));
+            ui.horizontal(|ui| {
+                restore = ui.button("Restore").clicked();
+                discard = ui.button("Discard").clicked();
+            });
+        });
+
+    if discard {
+        *offer = None;
+        return Some(RecoveryChoice::Discard);
+    }
+    if restore {
+        return offer.take().map(RecoveryChoice::Restore);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::SystemTime;
+    use super::*;
+
+    fn recovery(scene: Option<&str>) -> Recovery {
+        Recovery {
+            scene: scene.map(PathBuf::from),
+            generation: 0,
+        }
+    }
+
+    /// Creates `name` in a directory of its own, modified at `seconds` past the epoch.
+    fn file_modified_at(name: &str, seconds: u64) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sdk-autosave-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        File::create(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn work_on_a_never_saved_scene_is_offered() {
+        let info_file = file_modified_at("never_saved.ron", 100);
+        assert!(is_newer_than_save(&recovery(None), None, &info_file));
+    }
+
+    #[test]
+    fn without_a_history_the_newer_file_wins() {
+        let scene_file = file_modified_at("level.scn.ron", 200);
+        let older = file_modified_at("older.ron", 100);
+        let newer = file_modified_at("newer.ron", 300);
+        let recovery = recovery(Some("scenes/level.scn.ron"));
+
+        assert!(!is_newer_than_save(&recovery, Some(&scene_file), &older));
+        assert!(is_newer_than_save(&recovery, Some(&scene_file), &newer));
+    }
+}
@@ -1,8 +1,18 @@
-use bevy::{ecs::reflect, input::mouse::{MouseMotion, MouseWheel}, prelude::*};
+use bevy::{ecs::reflect, input::mouse::{MouseMotion, MouseWheel}, prelude::*, window::PrimaryWindow};
+use bevy_egui::EguiContext;
+use domain::LevelInfo;
 use smart_default::SmartDefault;
+use crate::editor_commands::wants_keyboard;
+use crate::editor_only::EditorOnly;
+use crate::keymap::{EditorAction, EditorKeymap};
+use crate::selection::EditorSelection;
+
+/// How far in front of the focused selection the camera stops.
+const FOCUS_DISTANCE: f32 = 4.0;
 
 #[derive(Component, SmartDefault, Reflect)]
 #[reflect(Component)]
+#[require(EditorOnly)]
 pub struct SdkCamera {
     #[default(2.0)]
     pub speed: f32,
@@ -12,36 +22,42 @@ pub struct SdkCamera {
 
 pub fn camera_movement(
     time: Res<Time>,
+    keymap: Res<EditorKeymap>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mouse_button_input: Res<ButtonInput<MouseButton>>,
     mut mouse_motion_events: EventReader<MouseMotion>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
     mut query: Query<(&mut SdkCamera, &mut Transform)>,
+    mut egui_context: Query<&mut EguiContext, With<PrimaryWindow>>,
 ) {
+    // Typing into an inspector field shouldn't fly the camera.
+    let typing = wants_keyboard(&mut egui_context);
+    // D is also part of Ctrl+D, which duplicates rather than flies.
+    let fly = |action| !typing && keymap.pressed_alone(action, &keyboard_input);
 
     for (mut camera, mut transform) in query.iter_mut() {
         let mut speed = camera.speed;
 
-        if keyboard_input.pressed(KeyCode::ShiftLeft) {
+        if keymap.pressed(EditorAction::FlyFast, &keyboard_input) {
             speed *= 4f32;
         }
-        if keyboard_input.pressed(KeyCode::ControlLeft) {
+        if keymap.pressed(EditorAction::FlySlow, &keyboard_input) {
             speed *= 0.5f32;
         }
 
-        if keyboard_input.pressed(KeyCode::KeyW) {
+        if fly(EditorAction::FlyForward) {
             let forward = transform.forward();
             transform.translation += forward * speed * time.delta_secs();
         }
-        if keyboard_input.pressed(KeyCode::KeyS) {
+        if fly(EditorAction::FlyBackward) {
             let back = transform.back();
             transform.translation += back * speed * time.delta_secs();
         }
-        if keyboard_input.pressed(KeyCode::KeyA) {
+        if fly(EditorAction::FlyLeft) {
             let left = transform.left();
             transform.translation += left * speed * time.delta_secs();
         }
-        if keyboard_input.pressed(KeyCode::KeyD) {
+        if fly(EditorAction::FlyRight) {
             let right = transform.right();
 
             transform.translation += right * speed * time.delta_secs();
@@ -85,4 +101,48 @@ pub fn camera_movement(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Puts the camera where the loaded level's [`LevelInfo::camera_start`] says.
+pub fn move_to_camera_start(world: &mut World) {
+    let Some(start) = world.get_resource::<LevelInfo>().and_then(|level| level.camera_start) else {
+        return;
+    };
+    let mut cameras = world.query_filtered::<&mut Transform, With<SdkCamera>>();
+    for mut transform in cameras.iter_mut(world) {
+        *transform = start;
+    }
+}
+
+/// Moves the camera in front of the selection's center, keeping its orientation.
+pub fn focus_selection(
+    keymap: Res<EditorKeymap>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    selection: Res<EditorSelection>,
+    targets: Query<&GlobalTransform>,
+    mut cameras: Query<&mut Transform, With<SdkCamera>>,
+    mut egui_context: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    // An "f" typed into an inspector field isn't a shortcut.
+    if wants_keyboard(&mut egui_context) {
+        return;
+    }
+    if !keymap.just_pressed(EditorAction::Focus, &keyboard_input) {
+        return;
+    }
+
+    let positions: Vec<Vec3> = selection
+        .as_slice()
+        .iter()
+        .filter_map(|entity| targets.get(entity).ok())
+        .map(GlobalTransform::translation)
+        .collect();
+    if positions.is_empty() {
+        return;
+    }
+    let center = positions.iter().sum::<Vec3>() / positions.len() as f32;
+
+    for mut transform in cameras.iter_mut() {
+        transform.translation = center + transform.back() * FOCUS_DISTANCE;
+    }
+}
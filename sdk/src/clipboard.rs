@@ -0,0 +1,163 @@
+use bevy::ecs::entity::EntityHashMap;
+use bevy::log::{info, warn};
+use bevy::prelude::{
+    AppTypeRegistry, BuildChildren, DynamicScene, DynamicSceneBuilder, Entity, Parent, Transform,
+    Vec3, World,
+};
+use bevy_egui::EguiClipboard;
+use domain::{level_scene_builder, SceneGuid};
+use crate::editor_commands::{with_descendants, HistoryManager};
+use crate::editor_only::EditorOnly;
+use crate::entity_commands::{is_parent, selection_roots, SpawnEntity};
+use crate::scene_file::{deserialize_scene, retain_serializable};
+use crate::selection::EditorSelection;
+
+/// How far pasted and duplicated entities are moved from the originals, so they don't overlap.
+const PASTE_OFFSET: Vec3 = Vec3::new(0.5, 0.0, 0.5);
+
+/// Reflected copy of the given hierarchies, with the roots detached from their parents.
+/// `builder` picks the components: duplicates stay in memory and keep asset handles, while the
+/// clipboard only takes what a saved level would.
+fn copy_hierarchies(world: &World, roots: &[Entity], builder: DynamicSceneBuilder) -> DynamicScene {
+    let entities: Vec<Entity> = roots
+        .iter()
+        .flat_map(|root| with_descendants(world, *root))
+        .filter(|entity| world.get::<EditorOnly>(*entity).is_none())
+        .collect();
+
+    let mut scene = builder
+        // Copies are new entities and get GUIDs of their own.
+        .deny_component::<SceneGuid>()
+        .extract_entities(entities.into_iter())
+        .build();
+    for entity in &mut scene.entities {
+        if roots.contains(&entity.entity) {
+            entity.components.retain(|component| !is_parent(component.as_ref()));
+        }
+    }
+    scene
+}
+
+/// Spawns the entities of `scene` under new ids as one undo step and selects the copies.
+/// `Parent` and `Children` are remapped to the copies; returns the copied roots with their originals.
+fn spawn_copies(world: &mut World, scene: &DynamicScene, label: &str) -> Vec<(Entity, Entity)> {
+    let roots: Vec<Entity> = scene
+        .entities
+        .iter()
+        .filter(|entity| !entity.components.iter().any(|component| is_parent(component.as_ref())))
+        .map(|entity| entity.entity)
+        .collect();
+
+    let mut entity_map = EntityHashMap::default();
+    if let Err(error) = scene.write_to_world(world, &mut entity_map) {
+        warn!("Failed to spawn copies: {error}");
+        return Vec::new();
+    }
+    let copies: Vec<(Entity, Entity)> = roots
+        .into_iter()
+        .filter_map(|root| Some((root, *entity_map.get(&root)?)))
+        .collect();
+
+    for (_, copy) in &copies {
+        if let Some(mut transform) = world.get_mut::<Transform>(*copy) {
+            transform.translation += PASTE_OFFSET;
+        }
+    }
+    world.resource_scope::<HistoryManager, _>(|_, mut history| {
+        history.begin_group(label);
+        for (_, copy) in &copies {
+            history.push(Box::new(SpawnEntity::spawned(*copy)));
+        }
+        history.end_group();
+    });
+
+    world
+        .resource_mut::<EditorSelection>()
+        .set(copies.iter().map(|(_, copy)| *copy));
+    copies
+}
+
+/// Puts the selected hierarchies on the system clipboard as a RON scene,
+/// which another SDK instance can paste.
+pub fn copy_selected(world: &mut World) {
+    let roots = selection_roots(world);
+    if roots.is_empty() {
+        return;
+    }
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    let mut scene = copy_hierarchies(world, &roots, level_scene_builder(world));
+    retain_serializable(&mut scene, &type_registry);
+    match scene.serialize(&type_registry) {
+        Ok(contents) => {
+            world.resource_mut::<EguiClipboard>().set_contents(&contents);
+            info!("Copied {} entities", scene.entities.len());
+        }
+        Err(error) => warn!("Failed to copy the selection: {error}"),
+    }
+}
+
+/// Spawns the entities on the system clipboard, if it holds any.
+pub fn paste_clipboard(world: &mut World) {
+    let Some(contents) = world.resource_mut::<EguiClipboard>().get_contents() else {
+        return;
+    };
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let scene = deserialize_scene(&contents, &type_registry.read());
+    let mut scene = match scene {
+        Ok(scene) => scene,
+        Err(error) => {
+            warn!("The clipboard doesn't hold entities: {error}");
+            return;
+        }
+    };
+    // Only entities are pasted, resources such as `LevelInfo` belong to the scene being edited.
+    scene.resources.clear();
+    spawn_copies(world, &scene, "Paste");
+}
+
+/// Copies the selected hierarchies next to the originals, under the same parents.
+pub fn duplicate_selected(world: &mut World) {
+    let roots = selection_roots(world);
+    if roots.is_empty() {
+        return;
+    }
+    let scene = copy_hierarchies(world, &roots, DynamicSceneBuilder::from_world(world));
+
+    // Undo snapshots the copies with whatever parent they have by then.
+    for (original, copy) in spawn_copies(world, &scene, "Duplicate selection") {
+        if let Some(parent) = world.get::<Parent>(original).map(Parent::get) {
+            world.entity_mut(parent).add_child(copy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Handle, Mesh, Mesh3d};
+    use super::*;
+
+    #[test]
+    fn duplicates_keep_their_meshes() {
+        let mut world = World::new();
+        let type_registry = AppTypeRegistry::default();
+        {
+            let mut type_registry = type_registry.write();
+            type_registry.register::<Transform>();
+            type_registry.register::<Mesh3d>();
+        }
+        world.insert_resource(type_registry);
+        world.insert_resource(HistoryManager::new());
+        world.init_resource::<EditorSelection>();
+        let mesh = Handle::<Mesh>::weak_from_u128(7);
+        let original = world.spawn((Transform::default(), Mesh3d(mesh.clone()))).id();
+        world.resource_mut::<EditorSelection>().set([original]);
+
+        duplicate_selected(&mut world);
+
+        let copy = world.resource::<EditorSelection>().as_slice()[0];
+        assert_ne!(copy, original);
+        assert_eq!(world.get::<Mesh3d>(copy).map(|copy| copy.0.clone()), Some(mesh));
+    }
+}
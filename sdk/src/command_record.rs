@@ -0,0 +1,409 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::{Entity, Transform, World};
+use bevy::reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer};
+use bevy::reflect::{PartialReflect, TypeRegistry};
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+use crate::editor_commands::{
+    ComponentChange, CompositeCommand, EditorCommand, PatchTarget, ReflectPatch,
+    ReflectPatchCommand, SetComponents, TransformChange, TransformTarget,
+};
+use crate::entity_commands::{DespawnEntity, EntitySnapshot, ReparentEntity, SpawnEntity};
+use crate::prefab::ApplyToPrefab;
+
+/// Serializable form of an [`EditorCommand`], used to persist the history next to a scene.
+///
+/// Entities are stored as the bits of the ids they had when the history was saved;
+/// reading the history back needs a map from those ids to the reloaded entities.
+/// Reflected values are stored as RON produced through the type registry.
+#[derive(Serialize, Deserialize)]
+pub enum CommandRecord {
+    Transform {
+        targets: Vec<(u64, Transform, Transform)>,
+    },
+    ReflectPatch {
+        label: String,
+        patches: Vec<PatchRecord>,
+    },
+    SetComponents {
+        label: String,
+        changes: Vec<ComponentRecord>,
+    },
+    Reparent {
+        entity: u64,
+        to: Option<u64>,
+        from: Option<u64>,
+        from_index: usize,
+        from_transform: Transform,
+        to_transform: Option<Transform>,
+    },
+    /// `entity` is set while the spawned entity exists, `snapshot` once the spawn is undone.
+    Spawn {
+        entity: Option<u64>,
+        snapshot: Option<SnapshotRecord>,
+    },
+    /// `snapshot` is set once the entity is deleted, i.e. on the undo stack.
+    Despawn {
+        entity: u64,
+        snapshot: Option<SnapshotRecord>,
+    },
+    Composite {
+        label: String,
+        commands: Vec<CommandRecord>,
+    },
+    /// Prefab file contents before and after, with the instance updates once applied.
+    ApplyToPrefab {
+        prefab: String,
+        source: u64,
+        before: String,
+        after: String,
+        instances: Option<Box<CommandRecord>>,
+    },
+}
+
+/// A deleted hierarchy, see [`EntitySnapshot`].
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    /// The entities as written by `DynamicScene::serialize`, without unserializable components.
+    pub scene: String,
+    /// Each scene entity with the id the rest of the history knows it by.
+    pub entities: Vec<(u64, u64)>,
+    /// Scene id of the root.
+    pub root: u64,
+    pub parent: Option<u64>,
+    pub sibling_index: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PatchRecord {
+    /// `None` for resources.
+    pub entity: Option<u64>,
+    pub type_path: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ComponentRecord {
+    pub entity: u64,
+    pub type_path: String,
+    /// `None` where the entity doesn't have the component.
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Undo and redo stacks as written to disk by [`crate::scene_file::save_scene`].
+#[derive(Serialize, Deserialize, Default)]
+pub struct HistoryRecord {
+    /// Oldest first.
+    pub undo: Vec<CommandRecord>,
+    /// In redo order, next first.
+    pub redo: Vec<CommandRecord>,
+    /// Saved entity id bits and the [`domain::SceneGuid`] each entity had, to find the
+    /// entities again once the scene is reloaded under new ids.
+    #[serde(default)]
+    pub guids: Vec<(u64, u128)>,
+    /// [`crate::scene_file::CurrentScene::generation`] when the history was written.
+    #[serde(default)]
+    pub generation: u64,
+}
+
+/// `level.scn.ron` keeps its history in `level.scn.history.ron`.
+pub fn history_path(scene_path: &Path) -> PathBuf {
+    scene_path.with_extension("history.ron")
+}
+
+pub fn serialize_value(value: &dyn PartialReflect, type_registry: &TypeRegistry) -> Option<String> {
+    ron::to_string(&TypedReflectSerializer::new(value, type_registry)).ok()
+}
+
+pub fn deserialize_value(
+    type_path: &str,
+    value: &str,
+    type_registry: &TypeRegistry,
+) -> Option<Box<dyn PartialReflect>> {
+    let registration = type_registry.get_with_type_path(type_path)?;
+    let mut deserializer = ron::Deserializer::from_str(value).ok()?;
+    TypedReflectDeserializer::new(registration, type_registry)
+        .deserialize(&mut deserializer)
+        .ok()
+}
+
+pub fn map_entity(bits: u64, entity_map: &EntityHashMap<Entity>) -> Option<Entity> {
+    entity_map.get(&Entity::try_from_bits(bits).ok()?).copied()
+}
+
+impl CommandRecord {
+    /// Rebuilds the command. Returns `None` if a referenced entity or type is gone.
+    pub fn into_command(
+        self,
+        type_registry: &TypeRegistry,
+        entity_map: &EntityHashMap<Entity>,
+    ) -> Option<Box<dyn EditorCommand>> {
+        let command: Box<dyn EditorCommand> = match self {
+            CommandRecord::Transform { targets } => Box::new(TransformChange {
+                targets: targets
+                    .into_iter()
+                    .map(|(entity, from, to)| {
+                        Some(TransformTarget {
+                            entity: map_entity(entity, entity_map)?,
+                            from,
+                            to,
+                        })
+                    })
+                    .collect::<Option<_>>()?,
+            }),
+            CommandRecord::ReflectPatch { label, patches } => Box::new(ReflectPatchCommand {
+                label,
+                patches: patches
+                    .into_iter()
+                    .map(|patch| {
+                        let type_id = type_registry.get_with_type_path(&patch.type_path)?.type_id();
+                        let target = match patch.entity {
+                            Some(entity) => PatchTarget::Component(map_entity(entity, entity_map)?, type_id),
+                            None => PatchTarget::Resource(type_id),
+                        };
+                        Some(ReflectPatch::new(
+                            target,
+                            deserialize_value(&patch.type_path, &patch.before, type_registry)?,
+                            deserialize_value(&patch.type_path, &patch.after, type_registry)?,
+                            type_registry,
+                        ))
+                    })
+                    .collect::<Option<_>>()?,
+            }),
+            CommandRecord::SetComponents { label, changes } => Box::new(SetComponents {
+                label,
+                changes: changes
+                    .into_iter()
+                    .map(|change| {
+                        let type_id = type_registry.get_with_type_path(&change.type_path)?.type_id();
+                        let deserialize = |value: Option<String>| match value {
+                            Some(value) => deserialize_value(&change.type_path, &value, type_registry).map(Some),
+                            None => Some(None),
+                        };
+                        Some(ComponentChange {
+                            entity: map_entity(change.entity, entity_map)?,
+                            type_id,
+                            before: deserialize(change.before)?,
+                            after: deserialize(change.after)?,
+                        })
+                    })
+                    .collect::<Option<_>>()?,
+            }),
+            CommandRecord::Reparent {
+                entity,
+                to,
+                from,
+                from_index,
+                from_transform,
+                to_transform,
+            } => {
+                let to = match to {
+                    Some(to) => Some(map_entity(to, entity_map)?),
+                    None => None,
+                };
+                let from = match from {
+                    Some(from) => Some(map_entity(from, entity_map)?),
+                    None => None,
+                };
+                Box::new(ReparentEntity {
+                    entity: map_entity(entity, entity_map)?,
+                    to,
+                    from,
+                    from_index,
+                    from_transform,
+                    to_transform,
+                })
+            }
+            CommandRecord::Spawn { entity, snapshot } => match (entity, snapshot) {
+                (_, Some(snapshot)) => Box::new(SpawnEntity::from_snapshot(
+                    EntitySnapshot::from_record(snapshot, type_registry, entity_map)?,
+                )),
+                (Some(entity), None) => Box::new(SpawnEntity::spawned(map_entity(entity, entity_map)?)),
+                (None, None) => return None,
+            },
+            CommandRecord::Despawn { entity, snapshot } => match snapshot {
+                Some(snapshot) => Box::new(DespawnEntity::despawned(EntitySnapshot::from_record(
+                    snapshot,
+                    type_registry,
+                    entity_map,
+                )?)),
+                None => Box::new(DespawnEntity::new(map_entity(entity, entity_map)?)),
+            },
+            CommandRecord::Composite { label, commands } => Box::new(CompositeCommand {
+                label,
+                commands: commands
+                    .into_iter()
+                    .map(|command| command.into_command(type_registry, entity_map))
+                    .collect::<Option<_>>()?,
+            }),
+            CommandRecord::ApplyToPrefab {
+                prefab,
+                source,
+                before,
+                after,
+                instances,
+            } => Box::new(ApplyToPrefab {
+                prefab,
+                source: map_entity(source, entity_map)?,
+                before,
+                after,
+                instances: match instances {
+                    Some(instances) => Some(instances.into_command(type_registry, entity_map)?),
+                    None => None,
+                },
+            }),
+        };
+        Some(command)
+    }
+}
+
+impl CommandRecord {
+    /// History ids of the entities kept in snapshots, see [`HistoryRecord::map_deleted_entities`].
+    fn deleted_entities(&self, entities: &mut Vec<u64>) {
+        match self {
+            CommandRecord::Spawn { snapshot: Some(snapshot), .. }
+            | CommandRecord::Despawn { snapshot: Some(snapshot), .. } => {
+                entities.extend(snapshot.entities.iter().map(|(_, entity)| *entity));
+            }
+            CommandRecord::Composite { commands, .. } => {
+                for command in commands {
+                    command.deleted_entities(entities);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl HistoryRecord {
+    /// Gives the entities that only exist in snapshots ids no live entity has, so the commands
+    /// referencing them can be restored; they get real entities once an undo respawns them.
+    pub fn map_deleted_entities(&self, world: &mut World, entity_map: &mut EntityHashMap<Entity>) {
+        let mut deleted = Vec::new();
+        for command in self.undo.iter().chain(&self.redo) {
+            command.deleted_entities(&mut deleted);
+        }
+        for bits in deleted {
+            let Ok(saved) = Entity::try_from_bits(bits) else {
+                continue;
+            };
+            entity_map.entry(saved).or_insert_with(|| {
+                // A despawned id isn't handed out again with the same generation.
+                let placeholder = world.spawn_empty().id();
+                world.despawn(placeholder);
+                placeholder
+            });
+        }
+    }
+
+    pub fn save(&self, scene_path: &Path) -> io::Result<()> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        std::fs::write(history_path(scene_path), contents)
+    }
+
+    pub fn load(scene_path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(history_path(scene_path))?;
+        ron::from_str(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Deletes the history saved next to the scene, if there is one.
+    pub fn remove(scene_path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(history_path(scene_path)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Size of a reflected value for [`EditorCommand::size_bytes`]: the length of its serialized
+/// form, which grows with strings, lists and maps the way the value's heap use does.
+/// Values that can't be serialized count with their inline size.
+pub fn reflect_size(value: &dyn PartialReflect, type_registry: &TypeRegistry) -> usize {
+    serialize_value(value, type_registry).map_or(std::mem::size_of_val(value), |value| value.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{AppTypeRegistry, Children, Parent, Vec3};
+    use crate::editor_commands::HistoryManager;
+    use super::*;
+
+    fn setup() -> World {
+        let mut world = World::new();
+        let type_registry = AppTypeRegistry::default();
+        {
+            let mut type_registry = type_registry.write();
+            type_registry.register::<Transform>();
+            type_registry.register::<Parent>();
+            type_registry.register::<Children>();
+        }
+        world.insert_resource(type_registry);
+        world
+    }
+
+    fn at(x: f32) -> Transform {
+        Transform::from_translation(Vec3::new(x, 0.0, 0.0))
+    }
+
+    fn translations(world: &mut World) -> Vec<f32> {
+        let mut translations: Vec<f32> = world
+            .query::<&Transform>()
+            .iter(world)
+            .map(|transform| transform.translation.x)
+            .collect();
+        translations.sort_by(f32::total_cmp);
+        translations
+    }
+
+    #[test]
+    fn history_round_trips_through_ron() {
+        let mut world = setup();
+        let mut history = HistoryManager::new();
+        let moved = world.spawn(at(0.0)).id();
+        let deleted = world.spawn(at(2.0)).id();
+
+        let change = TransformChange::capture(&world, [(moved, at(1.0))]).unwrap();
+        history.execute(Box::new(change), &mut world).unwrap();
+        history.execute(Box::new(DespawnEntity::new(deleted)), &mut world).unwrap();
+        let spawn = SpawnEntity::new(|world: &mut World| world.spawn(at(3.0)).id());
+        history.execute(Box::new(spawn), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+        assert_eq!(translations(&mut world), [1.0]);
+
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        let record = history.to_record(&type_registry.read());
+        assert_eq!((record.undo.len(), record.redo.len()), (2, 1));
+        let saved = ron::to_string(&record).unwrap();
+
+        // Reloaded as if `moved` was found again by its GUID.
+        let record: HistoryRecord = ron::from_str(&saved).unwrap();
+        let mut entity_map = EntityHashMap::default();
+        entity_map.insert(moved, moved);
+        record.map_deleted_entities(&mut world, &mut entity_map);
+        let mut restored = HistoryManager::new();
+        restored.restore(record, &type_registry.read(), &entity_map);
+        assert_eq!((restored.undo_stack().len(), restored.redo_stack().len()), (2, 1));
+
+        restored.redo(&mut world).unwrap();
+        assert_eq!(translations(&mut world), [1.0, 3.0]);
+        restored.undo(&mut world).unwrap();
+        restored.undo(&mut world).unwrap();
+        assert_eq!(translations(&mut world), [1.0, 2.0]);
+        restored.undo(&mut world).unwrap();
+        assert_eq!(translations(&mut world), [0.0, 2.0]);
+    }
+
+    #[test]
+    fn reflect_size_grows_with_the_value() {
+        let type_registry = TypeRegistry::default();
+        let short = reflect_size(&String::from("a"), &type_registry);
+        let long = reflect_size(&"a".repeat(1000), &type_registry);
+        assert!(long >= 1000);
+        assert!(long > short);
+    }
+}
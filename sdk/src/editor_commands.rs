@@ -1,87 +1,1450 @@
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::time::Duration;
+use bevy::ecs::component::Tick;
+use bevy::ecs::entity::EntityHashMap;
 use bevy::ecs::system::SystemState;
 use bevy::input::ButtonInput;
-use bevy::prelude::{Commands, Component, Entity, KeyCode, Res, ResMut, Resource, Transform, World};
+use bevy::log::warn;
+use bevy::prelude::{
+    AppTypeRegistry, Children, Component, Entity, Event, EventWriter, KeyCode, Query,
+    ReflectComponent, ReflectResource, ResMut, Resource, Transform, With, World,
+};
+use bevy::reflect::{PartialReflect, ReflectRef, TypeRegistry};
+use bevy::utils::{HashMap, Instant};
+use bevy::window::PrimaryWindow;
+use bevy_egui::EguiContext;
+use crate::command_record::{
+    reflect_size, serialize_value, CommandRecord, ComponentRecord, HistoryRecord, PatchRecord,
+};
+use crate::clipboard::{copy_selected, duplicate_selected, paste_clipboard};
+use crate::entity_commands::delete_selected;
+use crate::keymap::{EditorAction, EditorKeymap};
+use crate::selection::EditorSelection;
 
 #[derive(Resource)]
 pub struct HistoryManager {
     undo_stack: Vec<Box<dyn EditorCommand>>,
     redo_stack: Vec<Box<dyn EditorCommand>>,
+    /// Open groups from [`Self::begin_group`], innermost last.
+    groups: Vec<CompositeCommand>,
+    last_recorded: Option<Instant>,
+    /// Commands recorded within this interval are offered to [`EditorCommand::merge`].
+    pub merge_window: Duration,
+    /// The oldest steps are evicted once either limit is exceeded.
+    pub max_steps: usize,
+    /// Approximate, based on [`EditorCommand::size_bytes`].
+    pub max_bytes: usize,
+    /// Whether [`save_scene`](crate::scene_file::save_scene) writes the history next to the scene.
+    pub save_with_scene: bool,
+    /// Undo stack depth at the last save, `None` once that state can't be reached anymore.
+    saved_at: Option<usize>,
+    /// Sent as Bevy events by [`send_history_events`].
+    events: Vec<(HistoryEventKind, CommandEvent)>,
 }
 
+/// Old ids of entities brought back by an undo or redo, mapped to the entities they were
+/// restored as. Restored entities are spawned fresh rather than reviving their old ids;
+/// [`HistoryManager`] drains this after each step and remaps its commands.
+#[derive(Resource, Default)]
+pub struct RestoredEntities(pub EntityHashMap<Entity>);
+
+#[derive(Clone, Copy)]
+enum HistoryEventKind {
+    Executed,
+    Undone,
+    Redone,
+}
+
+/// Which command ran and what it touched.
+#[derive(Clone, Debug)]
+pub struct CommandEvent {
+    pub label: String,
+    pub entities: Vec<Entity>,
+}
+
+impl CommandEvent {
+    fn of(command: &dyn EditorCommand) -> Self {
+        Self {
+            label: command.label(),
+            entities: command.entities(),
+        }
+    }
+}
+
+/// A new step was recorded, whether executed through [`HistoryManager`] or pushed after the fact.
+/// Commands inside a group are reported once, as the whole group.
+#[derive(Event, Clone, Debug)]
+pub struct CommandExecuted(pub CommandEvent);
+
+#[derive(Event, Clone, Debug)]
+pub struct CommandUndone(pub CommandEvent);
+
+#[derive(Event, Clone, Debug)]
+pub struct CommandRedone(pub CommandEvent);
+
 impl HistoryManager {
     pub fn new() -> Self {
         Self {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
+            groups: Vec::new(),
+            last_recorded: None,
+            merge_window: Duration::from_millis(500),
+            max_steps: 256,
+            max_bytes: 64 * 1024 * 1024,
+            save_with_scene: true,
+            saved_at: Some(0),
+            events: Vec::new(),
         }
     }
 
-    pub fn execute(&mut self, mut command: Box<dyn EditorCommand>, commands: &mut Commands) {
-        command.execute(commands);
-        self.undo_stack.push(command);
+    /// Marks the current state as saved, see [`Self::is_dirty`].
+    pub fn mark_saved(&mut self) {
+        self.saved_at = Some(self.undo_stack.len());
+    }
+
+    /// Marks the world as differing from the saved file in every state, e.g. after recovering
+    /// work that was never saved.
+    pub fn mark_unsaved(&mut self) {
+        self.saved_at = None;
+    }
+
+    /// Whether the world differs from the last save, taking undo and redo into account.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_at != Some(self.undo_stack.len())
+    }
+
+    /// Runs the command and records it. A command that fails is not recorded.
+    pub fn execute(
+        &mut self,
+        mut command: Box<dyn EditorCommand>,
+        world: &mut World,
+    ) -> Result<(), CommandError> {
+        command.execute(world)?;
+        self.push(command);
+        self.map_restored_entities(world);
+        Ok(())
+    }
+
+    /// Records a command whose effect has already been applied to the world,
+    /// e.g. a gizmo drag that moved the entity interactively.
+    pub fn push(&mut self, command: Box<dyn EditorCommand>) {
+        if let Some(group) = self.groups.last_mut() {
+            group.commands.push(command);
+            return;
+        }
+
+        self.events.push((HistoryEventKind::Executed, CommandEvent::of(&*command)));
+
+        let now = Instant::now();
+        let recent = self
+            .last_recorded
+            .is_some_and(|last| now.duration_since(last) <= self.merge_window);
+        self.last_recorded = Some(now);
         self.redo_stack.clear(); // Очистка redo після нової дії
+
+        // A save point among the undone steps is gone with the redo stack.
+        if self.saved_at.is_some_and(|saved_at| saved_at > self.undo_stack.len()) {
+            self.saved_at = None;
+        }
+
+        if recent {
+            let depth = self.undo_stack.len();
+            if let Some(last) = self.undo_stack.last_mut() {
+                if last.merge(&*command) {
+                    // The merged step no longer ends in the saved state.
+                    if self.saved_at == Some(depth) {
+                        self.saved_at = None;
+                    }
+                    self.evict();
+                    return;
+                }
+            }
+        }
+        self.undo_stack.push(command);
+        self.evict();
     }
 
-    pub fn undo(&mut self, commands: &mut Commands) {
+    /// Drops the oldest undo steps until the history fits into `max_steps` and `max_bytes`.
+    /// The most recent step is always kept.
+    fn evict(&mut self) {
+        let mut bytes: usize = self
+            .undo_stack
+            .iter()
+            .chain(&self.redo_stack)
+            .map(|command| command.size_bytes())
+            .sum();
+        let mut evicted = 0;
+        while self.undo_stack.len() - evicted > 1
+            && (self.undo_stack.len() - evicted > self.max_steps || bytes > self.max_bytes)
+        {
+            bytes -= self.undo_stack[evicted].size_bytes();
+            evicted += 1;
+        }
+        self.undo_stack.drain(..evicted);
+        self.saved_at = self.saved_at.and_then(|saved_at| saved_at.checked_sub(evicted));
+    }
+
+    /// Serializable copy of the history. Each stack is cut at the first command that has
+    /// no [`EditorCommand::record`], since the steps beyond it couldn't be replayed.
+    /// `guids` and `generation` are left for the scene saving code to fill in.
+    pub fn to_record(&self, type_registry: &TypeRegistry) -> HistoryRecord {
+        let mut undo: Vec<CommandRecord> = self
+            .undo_stack()
+            .rev()
+            .map_while(|command| command.record(type_registry))
+            .collect();
+        undo.reverse();
+        let redo = self
+            .redo_stack()
+            .map_while(|command| command.record(type_registry))
+            .collect();
+        HistoryRecord {
+            undo,
+            redo,
+            guids: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Replaces the history with `record`, remapping saved entity ids through `entity_map`.
+    pub fn restore(
+        &mut self,
+        record: HistoryRecord,
+        type_registry: &TypeRegistry,
+        entity_map: &EntityHashMap<Entity>,
+    ) {
+        let mut undo_stack: Vec<_> = record
+            .undo
+            .into_iter()
+            .rev()
+            .map_while(|command| command.into_command(type_registry, entity_map))
+            .collect();
+        undo_stack.reverse();
+        let mut redo_stack: Vec<_> = record
+            .redo
+            .into_iter()
+            .map_while(|command| command.into_command(type_registry, entity_map))
+            .collect();
+        redo_stack.reverse();
+
+        self.undo_stack = undo_stack;
+        self.redo_stack = redo_stack;
+        self.groups.clear();
+        // Not yet sent, but about entities of the world the record replaces.
+        self.events.clear();
+        self.last_recorded = None;
+        self.mark_saved();
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.groups.clear();
+        self.last_recorded = None;
+        self.saved_at = Some(0);
+    }
+
+    /// Records the next command as a new step even within [`Self::merge_window`],
+    /// e.g. for a gizmo drag that shouldn't fold into the drag before it.
+    pub fn break_merge(&mut self) {
+        self.last_recorded = None;
+    }
+
+    /// Starts collecting executed and pushed commands into a single undo step.
+    /// Groups may be nested; only the outermost one reaches the undo stack.
+    pub fn begin_group(&mut self, label: impl Into<String>) {
+        self.groups.push(CompositeCommand::new(label));
+    }
+
+    /// Closes the innermost group opened by [`Self::begin_group`]. Empty groups are discarded.
+    pub fn end_group(&mut self) {
+        let Some(group) = self.groups.pop() else {
+            warn!("HistoryManager::end_group called without a matching begin_group");
+            return;
+        };
+        if !group.commands.is_empty() {
+            self.push(Box::new(group));
+        }
+    }
+
+    /// Executed commands, oldest first. The last one is the current state.
+    pub fn undo_stack(&self) -> impl DoubleEndedIterator<Item = &dyn EditorCommand> + ExactSizeIterator {
+        self.undo_stack.iter().map(|command| command.as_ref() as &dyn EditorCommand)
+    }
+
+    /// Undone commands in the order they would be redone.
+    pub fn redo_stack(&self) -> impl DoubleEndedIterator<Item = &dyn EditorCommand> + ExactSizeIterator {
+        self.redo_stack.iter().rev().map(|command| command.as_ref() as &dyn EditorCommand)
+    }
+
+    /// Undoes the last command. If it fails (e.g. its entity was despawned)
+    /// the command is dropped from the history and the error is returned.
+    pub fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.last_recorded = None;
         if let Some(mut command) = self.undo_stack.pop() {
-            command.undo(commands);
+            // Taken before undoing, while e.g. a spawn still knows its entity.
+            let event = CommandEvent::of(&*command);
+            command.undo(world)?;
             self.redo_stack.push(command);
+            self.events.push((HistoryEventKind::Undone, event));
+            self.map_restored_entities(world);
         }
+        Ok(())
     }
 
-    pub fn redo(&mut self, commands: &mut Commands) {
+    /// Redoes the last undone command, dropping it on failure like [`Self::undo`].
+    pub fn redo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.last_recorded = None;
         if let Some(mut command) = self.redo_stack.pop() {
-            command.execute(commands);
+            command.execute(world)?;
+            self.events.push((HistoryEventKind::Redone, CommandEvent::of(&*command)));
             self.undo_stack.push(command);
+            self.map_restored_entities(world);
+        }
+        Ok(())
+    }
+
+    /// Replaces entity ids in every recorded and grouped command, e.g. after entities
+    /// were despawned and spawned again from a snapshot.
+    pub fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        for command in self.undo_stack.iter_mut().chain(&mut self.redo_stack) {
+            command.map_entities(entity_map);
+        }
+        for group in &mut self.groups {
+            group.map_entities(entity_map);
+        }
+        for (_, event) in &mut self.events {
+            for entity in &mut event.entities {
+                *entity = entity_map.get(entity).copied().unwrap_or(*entity);
+            }
+        }
+    }
+
+    /// Applies and clears [`RestoredEntities`].
+    fn map_restored_entities(&mut self, world: &mut World) {
+        let Some(mut restored) = world.get_resource_mut::<RestoredEntities>() else {
+            return;
+        };
+        if restored.0.is_empty() {
+            return;
+        }
+        let entity_map = std::mem::take(&mut restored.0);
+        self.map_entities(&entity_map);
+        if let Some(mut selection) = world.get_resource_mut::<EditorSelection>() {
+            selection.map_entities(&entity_map);
         }
     }
 }
 
-pub trait EditorCommand: Send + Sync {
-    fn execute(&mut self, commands: &mut Commands);
-    fn undo(&mut self, commands: &mut Commands);
+pub trait EditorCommand: AsAny + Send + Sync {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError>;
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError>;
+
+    /// Human-readable description shown in the History tab.
+    fn label(&self) -> String;
+
+    /// Entities the command changes, reported with [`CommandExecuted`] and friends.
+    fn entities(&self) -> Vec<Entity> {
+        Vec::new()
+    }
+
+    /// Approximate memory held by the command, used to bound the history.
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
+    /// Serializable form for persisting the history. Commands returning `None` end the saved history.
+    fn record(&self, _type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        None
+    }
+
+    /// Folds `next`, which was recorded right after `self`, into `self`.
+    /// Returns `false` if the two commands don't touch the same target.
+    fn merge(&mut self, _next: &dyn EditorCommand) -> bool {
+        false
+    }
+
+    /// Replaces the entities the command refers to, see [`RestoredEntities`].
+    /// Ids missing from `entity_map` stay as they are.
+    fn map_entities(&mut self, _entity_map: &EntityHashMap<Entity>) {}
 }
 
-pub fn handle_input(
-    mut world: &mut World,
-) {
+/// Lets [`EditorCommand::merge`] downcast the other command.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
 
-    let mut state: SystemState<(
-        Res<ButtonInput<KeyCode>>,
-        ResMut<HistoryManager>,
-        Commands,
-    )> = SystemState::new(world);
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
-    let (keyboard_input, mut history, mut commands) = state.get_mut(world);
+/// Several commands applied and undone as one step.
+pub struct CompositeCommand {
+    pub label: String,
+    pub commands: Vec<Box<dyn EditorCommand>>,
+}
+
+impl CompositeCommand {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            commands: Vec::new(),
+        }
+    }
 
-    if !keyboard_input.pressed(KeyCode::KeyZ) {
+    /// Remaps the sub-commands after one of them restored entities, so the ones that run
+    /// next find them. [`HistoryManager`] still sees the same map once the whole step is done.
+    fn map_restored_entities(&mut self, world: &World) {
+        let Some(restored) = world.get_resource::<RestoredEntities>() else {
+            return;
+        };
+        if restored.0.is_empty() {
+            return;
+        }
+        for command in &mut self.commands {
+            command.map_entities(&restored.0);
+        }
+    }
+}
+
+impl EditorCommand for CompositeCommand {
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        for entity in self.commands.iter().flat_map(|command| command.entities()) {
+            if !entities.contains(&entity) {
+                entities.push(entity);
+            }
+        }
+        entities
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.commands.iter().map(|command| command.size_bytes()).sum::<usize>()
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::Composite {
+            label: self.label.clone(),
+            commands: self
+                .commands
+                .iter()
+                .map(|command| command.record(type_registry))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        for index in 0..self.commands.len() {
+            if let Err(error) = self.commands[index].execute(world) {
+                // Roll back what already ran so the world isn't left half-applied.
+                for undo in (0..index).rev() {
+                    let _ = self.commands[undo].undo(world);
+                    self.map_restored_entities(world);
+                }
+                return Err(error);
+            }
+            self.map_restored_entities(world);
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        for index in (0..self.commands.len()).rev() {
+            if let Err(error) = self.commands[index].undo(world) {
+                for redo in index + 1..self.commands.len() {
+                    let _ = self.commands[redo].execute(world);
+                    self.map_restored_entities(world);
+                }
+                return Err(error);
+            }
+            self.map_restored_entities(world);
+        }
+        Ok(())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        for command in &mut self.commands {
+            command.map_entities(entity_map);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    EntityNotFound(Entity),
+    MissingComponent {
+        entity: Entity,
+        component: &'static str,
+    },
+    Unregistered(TypeId),
+    Scene(String),
+    Io(String),
+    /// Parenting `entity` to `parent` would put it under itself.
+    HierarchyCycle {
+        entity: Entity,
+        parent: Entity,
+    },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::EntityNotFound(entity) => write!(f, "entity {entity} no longer exists"),
+            CommandError::MissingComponent { entity, component } => {
+                write!(f, "entity {entity} has no {component} component")
+            }
+            CommandError::Unregistered(type_id) => {
+                write!(f, "type {type_id:?} is not registered for reflection")
+            }
+            CommandError::Scene(error) => write!(f, "failed to restore entities: {error}"),
+            CommandError::Io(error) => write!(f, "failed to write file: {error}"),
+            CommandError::HierarchyCycle { entity, parent } => {
+                write!(f, "can't parent {entity} to itself or its descendant {parent}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Returns an error unless `entity` exists and has a `T` component.
+pub fn require_component<T: Component>(world: &World, entity: Entity) -> Result<(), CommandError> {
+    let entity_ref = world
+        .get_entity(entity)
+        .map_err(|_| CommandError::EntityNotFound(entity))?;
+    if !entity_ref.contains::<T>() {
+        return Err(CommandError::MissingComponent {
+            entity,
+            component: std::any::type_name::<T>(),
+        });
+    }
+    Ok(())
+}
+
+pub fn send_history_events(
+    mut history: ResMut<HistoryManager>,
+    mut executed: EventWriter<CommandExecuted>,
+    mut undone: EventWriter<CommandUndone>,
+    mut redone: EventWriter<CommandRedone>,
+) {
+    if history.events.is_empty() {
+        return;
+    }
+    for (kind, event) in history.events.drain(..) {
+        match kind {
+            HistoryEventKind::Executed => {
+                executed.send(CommandExecuted(event));
+            }
+            HistoryEventKind::Undone => {
+                undone.send(CommandUndone(event));
+            }
+            HistoryEventKind::Redone => {
+                redone.send(CommandRedone(event));
+            }
+        }
+    }
+}
+
+pub fn handle_input(world: &mut World) {
+    if egui_wants_keyboard(world) {
         return;
     }
-    if keyboard_input.pressed(KeyCode::KeyZ) {
-        history.undo(&mut commands);
-    } else if keyboard_input.pressed(KeyCode::KeyY) {
-        history.redo(&mut commands);
+
+    let keyboard_input = world.resource::<ButtonInput<KeyCode>>();
+    let keymap = world.resource::<EditorKeymap>();
+    let delete = keymap.just_pressed(EditorAction::Delete, keyboard_input);
+    let copy = keymap.just_pressed(EditorAction::Copy, keyboard_input);
+    let paste = keymap.just_pressed(EditorAction::Paste, keyboard_input);
+    let duplicate = keymap.just_pressed(EditorAction::Duplicate, keyboard_input);
+    let undo = keymap.just_pressed(EditorAction::Undo, keyboard_input);
+    let redo = keymap.just_pressed(EditorAction::Redo, keyboard_input);
+
+    if delete {
+        delete_selected(world);
+    }
+    if copy {
+        copy_selected(world);
     }
+    if paste {
+        paste_clipboard(world);
+    }
+    if duplicate {
+        duplicate_selected(world);
+    }
+
+    if !undo && !redo {
+        return;
+    }
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        let result = if undo {
+            history.undo(world)
+        } else {
+            history.redo(world)
+        };
+        if let Err(error) = result {
+            warn!("Dropped editor command from history: {error}");
+        }
+    });
+}
+
+/// True while an egui widget (e.g. an inspector text field) has keyboard focus.
+pub fn egui_wants_keyboard(world: &mut World) -> bool {
+    let mut state = SystemState::<Query<&mut EguiContext, With<PrimaryWindow>>>::new(world);
+    wants_keyboard(&mut state.get_mut(world))
+}
+
+/// [`egui_wants_keyboard`] for systems that query the primary window's egui context themselves.
+pub fn wants_keyboard(contexts: &mut Query<&mut EguiContext, With<PrimaryWindow>>) -> bool {
+    contexts
+        .get_single_mut()
+        .is_ok_and(|mut context| context.get_mut().wants_keyboard_input())
+}
 
-    state.apply(world);
+/// A single entity moved by a [`TransformChange`].
+pub struct TransformTarget {
+    pub entity: Entity,
+    pub from: Transform,
+    pub to: Transform,
 }
 
-struct TransformChange {
-    entity: Entity,
-    from: Transform,
-    to: Transform
+/// Moves one or more entities at once, so a multi-selection drag is a single undo step.
+pub struct TransformChange {
+    pub targets: Vec<TransformTarget>,
 }
+
+impl TransformChange {
+    /// Builds a change towards `to`, reading the current transforms from the world as `from`.
+    pub fn capture(
+        world: &World,
+        to: impl IntoIterator<Item = (Entity, Transform)>,
+    ) -> Result<Self, CommandError> {
+        let targets = to
+            .into_iter()
+            .map(|(entity, to)| {
+                require_component::<Transform>(world, entity)?;
+                Ok(TransformTarget {
+                    entity,
+                    from: *world.get::<Transform>(entity).unwrap(),
+                    to,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { targets })
+    }
+
+    fn apply(&self, world: &mut World, to: fn(&TransformTarget) -> Transform) -> Result<(), CommandError> {
+        // Validate everything first so a missing entity doesn't leave the selection half-moved.
+        for target in &self.targets {
+            require_component::<Transform>(world, target.entity)?;
+        }
+        for target in &self.targets {
+            *world.get_mut::<Transform>(target.entity).unwrap() = to(target);
+        }
+        Ok(())
+    }
+}
+
 impl EditorCommand for TransformChange {
-    fn execute(&mut self, commands: &mut Commands) {
-        /*if let Some(mut transform) = world.get_mut::<Transform>(self.entity) {
-            *transform = self.to;
-        }*/
-        commands.entity(self.entity).remove::<Transform>();
-        commands.entity(self.entity).insert(self.to);
+    fn label(&self) -> String {
+        match self.targets.as_slice() {
+            [target] => format!("Move {}", target.entity),
+            targets => format!("Move {} entities", targets.len()),
+        }
     }
 
-    fn undo(&mut self, commands: &mut Commands) {
-        commands.entity(self.entity).remove::<Transform>();
-        commands.entity(self.entity).insert(self.from);
+    fn entities(&self) -> Vec<Entity> {
+        self.targets.iter().map(|target| target.entity).collect()
     }
-}
\ No newline at end of file
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.targets.len() * std::mem::size_of::<TransformTarget>()
+    }
+
+    fn record(&self, _type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::Transform {
+            targets: self
+                .targets
+                .iter()
+                .map(|target| (target.entity.to_bits(), target.from, target.to))
+                .collect(),
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |target| target.to)
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |target| target.from)
+    }
+
+    fn merge(&mut self, next: &dyn EditorCommand) -> bool {
+        let Some(next) = next.as_any().downcast_ref::<TransformChange>() else {
+            return false;
+        };
+        // Only repeated edits of the same entities and fields, e.g. nudging the same axis.
+        let same_targets = self.targets.len() == next.targets.len()
+            && self.targets.iter().zip(&next.targets).all(|(target, next)| {
+                target.entity == next.entity
+                    && changed_fields(&target.from, &target.to) == changed_fields(&next.from, &next.to)
+            });
+        if !same_targets {
+            return false;
+        }
+        for (target, next) in self.targets.iter_mut().zip(&next.targets) {
+            target.to = next.to;
+        }
+        true
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        for target in &mut self.targets {
+            target.entity = entity_map.get(&target.entity).copied().unwrap_or(target.entity);
+        }
+    }
+}
+
+/// What a [`ReflectPatch`] writes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PatchTarget {
+    Component(Entity, TypeId),
+    Resource(TypeId),
+}
+
+pub struct ReflectPatch {
+    pub target: PatchTarget,
+    pub before: Box<dyn PartialReflect>,
+    pub after: Box<dyn PartialReflect>,
+    /// Serialized lengths of `before` and `after`, for [`EditorCommand::size_bytes`].
+    sizes: (usize, usize),
+    /// The fields that differ between `before` and `after`, see [`changed_fields`].
+    /// Only patches changing the same fields are merged.
+    fields: Vec<String>,
+}
+
+impl ReflectPatch {
+    pub fn new(
+        target: PatchTarget,
+        before: Box<dyn PartialReflect>,
+        after: Box<dyn PartialReflect>,
+        type_registry: &TypeRegistry,
+    ) -> Self {
+        let sizes = (
+            reflect_size(before.as_ref(), type_registry),
+            reflect_size(after.as_ref(), type_registry),
+        );
+        let fields = changed_fields(before.as_ref(), after.as_ref());
+        Self {
+            target,
+            before,
+            after,
+            sizes,
+            fields,
+        }
+    }
+}
+
+/// Paths of the fields that differ between two values of the same type, or an empty path
+/// when the value isn't a struct and differs as a whole.
+pub fn changed_fields(before: &dyn PartialReflect, after: &dyn PartialReflect) -> Vec<String> {
+    let differs = |a: &dyn PartialReflect, b: &dyn PartialReflect| a.reflect_partial_eq(b) != Some(true);
+
+    match (before.reflect_ref(), after.reflect_ref()) {
+        (ReflectRef::Struct(before), ReflectRef::Struct(after)) => (0..before.field_len())
+            .filter_map(|index| {
+                let name = before.name_at(index)?;
+                let field = after.field(name)?;
+                differs(before.field_at(index)?, field).then(|| format!(".{name}"))
+            })
+            .collect(),
+        (ReflectRef::TupleStruct(before), ReflectRef::TupleStruct(after)) => (0..before.field_len())
+            .filter_map(|index| differs(before.field(index)?, after.field(index)?).then(|| format!(".{index}")))
+            .collect(),
+        _ if differs(before, after) => vec![String::new()],
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces reflected component or resource values, e.g. an edit made in the inspector.
+/// Works for any type registered with `ReflectComponent` or `ReflectResource`.
+pub struct ReflectPatchCommand {
+    pub label: String,
+    pub patches: Vec<ReflectPatch>,
+}
+
+impl ReflectPatchCommand {
+    fn apply(
+        &self,
+        world: &mut World,
+        value: fn(&ReflectPatch) -> &dyn PartialReflect,
+    ) -> Result<(), CommandError> {
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        let type_registry = type_registry.read();
+
+        for patch in &self.patches {
+            match patch.target {
+                PatchTarget::Component(entity, type_id) => {
+                    type_registry
+                        .get_type_data::<ReflectComponent>(type_id)
+                        .ok_or(CommandError::Unregistered(type_id))?;
+                    world
+                        .get_entity(entity)
+                        .map_err(|_| CommandError::EntityNotFound(entity))?;
+                }
+                PatchTarget::Resource(type_id) => {
+                    type_registry
+                        .get_type_data::<ReflectResource>(type_id)
+                        .ok_or(CommandError::Unregistered(type_id))?;
+                }
+            }
+        }
+
+        for patch in &self.patches {
+            match patch.target {
+                PatchTarget::Component(entity, type_id) => {
+                    let reflect_component = type_registry.get_type_data::<ReflectComponent>(type_id).unwrap();
+                    reflect_component.insert(&mut world.entity_mut(entity), value(patch), &type_registry);
+                }
+                PatchTarget::Resource(type_id) => {
+                    let reflect_resource = type_registry.get_type_data::<ReflectResource>(type_id).unwrap();
+                    reflect_resource.insert(world, value(patch), &type_registry);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EditorCommand for ReflectPatchCommand {
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        for patch in &self.patches {
+            if let PatchTarget::Component(entity, _) = patch.target {
+                if !entities.contains(&entity) {
+                    entities.push(entity);
+                }
+            }
+        }
+        entities
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .patches
+                .iter()
+                .map(|patch| patch.sizes.0 + patch.sizes.1)
+                .sum::<usize>()
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        let patches = self
+            .patches
+            .iter()
+            .map(|patch| {
+                let (entity, type_id) = match patch.target {
+                    PatchTarget::Component(entity, type_id) => (Some(entity.to_bits()), type_id),
+                    PatchTarget::Resource(type_id) => (None, type_id),
+                };
+                Some(PatchRecord {
+                    entity,
+                    type_path: type_registry.get(type_id)?.type_info().type_path().to_string(),
+                    before: serialize_value(patch.before.as_ref(), type_registry)?,
+                    after: serialize_value(patch.after.as_ref(), type_registry)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(CommandRecord::ReflectPatch {
+            label: self.label.clone(),
+            patches,
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |patch| patch.after.as_ref())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |patch| patch.before.as_ref())
+    }
+
+    fn merge(&mut self, next: &dyn EditorCommand) -> bool {
+        let Some(next) = next.as_any().downcast_ref::<ReflectPatchCommand>() else {
+            return false;
+        };
+        let same_targets = self.patches.len() == next.patches.len()
+            && self
+                .patches
+                .iter()
+                .zip(&next.patches)
+                .all(|(patch, next)| patch.target == next.target && patch.fields == next.fields);
+        if !same_targets {
+            return false;
+        }
+        for (patch, next) in self.patches.iter_mut().zip(&next.patches) {
+            patch.after = next.after.clone_value();
+            patch.sizes.1 = next.sizes.1;
+        }
+        true
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        for patch in &mut self.patches {
+            if let PatchTarget::Component(entity, type_id) = patch.target {
+                let entity = entity_map.get(&entity).copied().unwrap_or(entity);
+                patch.target = PatchTarget::Component(entity, type_id);
+            }
+        }
+    }
+}
+
+/// A component inserted, replaced or removed by a [`SetComponents`]; `None` means absent.
+pub struct ComponentChange {
+    pub entity: Entity,
+    pub type_id: TypeId,
+    pub before: Option<Box<dyn PartialReflect>>,
+    pub after: Option<Box<dyn PartialReflect>>,
+}
+
+/// Inserts or removes reflected components, for changes a [`ReflectPatchCommand`] can't express
+/// because the component isn't there on one side, e.g. linking entities to a prefab.
+pub struct SetComponents {
+    pub label: String,
+    pub changes: Vec<ComponentChange>,
+}
+
+impl SetComponents {
+    fn apply(
+        &self,
+        world: &mut World,
+        value: fn(&ComponentChange) -> Option<&dyn PartialReflect>,
+    ) -> Result<(), CommandError> {
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        let type_registry = type_registry.read();
+
+        for change in &self.changes {
+            type_registry
+                .get_type_data::<ReflectComponent>(change.type_id)
+                .ok_or(CommandError::Unregistered(change.type_id))?;
+            world
+                .get_entity(change.entity)
+                .map_err(|_| CommandError::EntityNotFound(change.entity))?;
+        }
+
+        for change in &self.changes {
+            let reflect_component = type_registry.get_type_data::<ReflectComponent>(change.type_id).unwrap();
+            let mut entity = world.entity_mut(change.entity);
+            match value(change) {
+                Some(value) => reflect_component.insert(&mut entity, value, &type_registry),
+                None => reflect_component.remove(&mut entity),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EditorCommand for SetComponents {
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        for change in &self.changes {
+            if !entities.contains(&change.entity) {
+                entities.push(change.entity);
+            }
+        }
+        entities
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.changes.len() * std::mem::size_of::<ComponentChange>()
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        let serialize = |value: &Option<Box<dyn PartialReflect>>| match value {
+            Some(value) => serialize_value(value.as_ref(), type_registry).map(Some),
+            None => Some(None),
+        };
+        let changes = self
+            .changes
+            .iter()
+            .map(|change| {
+                Some(ComponentRecord {
+                    entity: change.entity.to_bits(),
+                    type_path: type_registry.get(change.type_id)?.type_info().type_path().to_string(),
+                    before: serialize(&change.before)?,
+                    after: serialize(&change.after)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(CommandRecord::SetComponents {
+            label: self.label.clone(),
+            changes,
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |change| change.after.as_deref())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.apply(world, |change| change.before.as_deref())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        for change in &mut self.changes {
+            change.entity = entity_map.get(&change.entity).copied().unwrap_or(change.entity);
+        }
+    }
+}
+
+/// Reflected values of what the inspector shows, kept across frames and diffed after each
+/// inspector pass into a [`ReflectPatchCommand`].
+///
+/// Values are only cloned or compared once their change ticks move, so a large selection costs
+/// little while nothing is being edited.
+#[derive(Default)]
+pub struct ReflectSnapshot {
+    values: Vec<(PatchTarget, Box<dyn PartialReflect>)>,
+    /// Values changed after this tick are newer than the snapshot.
+    tick: Tick,
+}
+
+impl ReflectSnapshot {
+    /// Every reflected component of `entities`.
+    pub fn entity_targets(
+        world: &World,
+        entities: impl IntoIterator<Item = Entity>,
+        type_registry: &TypeRegistry,
+    ) -> Vec<PatchTarget> {
+        let mut targets = Vec::new();
+        for entity in entities {
+            if world.get_entity(entity).is_err() {
+                continue;
+            }
+            for info in world.inspect_entity(entity) {
+                let Some(type_id) = info.type_id() else {
+                    continue;
+                };
+                if type_registry.get_type_data::<ReflectComponent>(type_id).is_some() {
+                    targets.push(PatchTarget::Component(entity, type_id));
+                }
+            }
+        }
+        targets
+    }
+
+    /// Tracks exactly `targets` from now on. Values that changed since the last call, e.g. through
+    /// a gizmo drag or undo, are taken over as they are, so they aren't reported by [`Self::diff`].
+    /// Call it right before the inspector.
+    pub fn update(&mut self, world: &mut World, targets: Vec<PatchTarget>, type_registry: &TypeRegistry) {
+        let mut previous: HashMap<PatchTarget, Box<dyn PartialReflect>> =
+            std::mem::take(&mut self.values).into_iter().collect();
+        for target in targets {
+            let value = match previous.remove(&target) {
+                Some(value) if !changed_since(world, target, self.tick) => value,
+                _ => match read_target(world, type_registry, target) {
+                    Some(current) => current.clone_value(),
+                    None => continue,
+                },
+            };
+            self.values.push((target, value));
+        }
+        // Changes made after this, i.e. by the inspector, get a newer tick.
+        self.tick = world.increment_change_tick();
+    }
+
+    /// What `target` was at the last [`Self::update`] or [`Self::diff`], if it's tracked.
+    pub fn before(&self, target: PatchTarget) -> Option<&dyn PartialReflect> {
+        self.values
+            .iter()
+            .find(|(tracked, _)| *tracked == target)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// The values changed since [`Self::update`], as a command. The snapshot takes them over.
+    /// Values that can't be compared through reflection are compared in serialized form.
+    pub fn diff(&mut self, world: &World, type_registry: &TypeRegistry) -> Option<ReflectPatchCommand> {
+        let mut patches = Vec::new();
+        for (target, before) in &mut self.values {
+            if !changed_since(world, *target, self.tick) {
+                continue;
+            }
+            let Some(current) = read_target(world, type_registry, *target) else {
+                continue;
+            };
+            let changed = match current.reflect_partial_eq(before.as_ref()) {
+                Some(equal) => !equal,
+                None => {
+                    let current = serialize_value(current, type_registry);
+                    current.is_none() || current != serialize_value(before.as_ref(), type_registry)
+                }
+            };
+            if changed {
+                let before = std::mem::replace(before, current.clone_value());
+                patches.push(ReflectPatch::new(*target, before, current.clone_value(), type_registry));
+            }
+        }
+
+        let first = patches.first()?;
+        let type_id = match first.target {
+            PatchTarget::Component(_, type_id) | PatchTarget::Resource(type_id) => type_id,
+        };
+        let same_type = patches.iter().all(|patch| match patch.target {
+            PatchTarget::Component(_, other) | PatchTarget::Resource(other) => other == type_id,
+        });
+        let label = match type_registry.get(type_id) {
+            Some(registration) if same_type => format!(
+                "Edit {}",
+                registration.type_info().type_path_table().short_path()
+            ),
+            _ => format!("Edit {} values", patches.len()),
+        };
+
+        Some(ReflectPatchCommand { label, patches })
+    }
+}
+
+/// Whether `target` was changed after `tick`. Missing targets count as unchanged.
+fn changed_since(world: &World, target: PatchTarget, tick: Tick) -> bool {
+    let ticks = match target {
+        PatchTarget::Component(entity, type_id) => world
+            .components()
+            .get_id(type_id)
+            .zip(world.get_entity(entity).ok())
+            .and_then(|(id, entity)| entity.get_change_ticks_by_id(id)),
+        PatchTarget::Resource(type_id) => world
+            .components()
+            .get_resource_id(type_id)
+            .and_then(|id| world.get_resource_change_ticks_by_id(id)),
+    };
+    ticks.is_some_and(|ticks| ticks.is_changed(tick, world.read_change_tick()))
+}
+
+fn read_target<'w>(
+    world: &'w World,
+    type_registry: &TypeRegistry,
+    target: PatchTarget,
+) -> Option<&'w dyn PartialReflect> {
+    let value = match target {
+        PatchTarget::Component(entity, type_id) => type_registry
+            .get_type_data::<ReflectComponent>(type_id)?
+            .reflect(world.get_entity(entity).ok()?)?,
+        PatchTarget::Resource(type_id) => type_registry
+            .get_type_data::<ReflectResource>(type_id)?
+            .reflect(world)?,
+    };
+    Some(value.as_partial_reflect())
+}
+
+/// `entity` followed by all of its descendants.
+pub fn with_descendants(world: &World, entity: Entity) -> Vec<Entity> {
+    let mut entities = vec![entity];
+    let mut index = 0;
+    while index < entities.len() {
+        if let Some(children) = world.get::<Children>(entities[index]) {
+            entities.extend(children.iter().copied());
+        }
+        index += 1;
+    }
+    entities
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Name, Vec3};
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Counter(i32);
+
+    /// Adds to [`Counter`]; consecutive adds merge.
+    struct Add(i32);
+
+    impl EditorCommand for Add {
+        fn label(&self) -> String {
+            format!("Add {}", self.0)
+        }
+
+        fn size_bytes(&self) -> usize {
+            100
+        }
+
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.resource_mut::<Counter>().0 += self.0;
+            Ok(())
+        }
+
+        fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.resource_mut::<Counter>().0 -= self.0;
+            Ok(())
+        }
+
+        fn merge(&mut self, next: &dyn EditorCommand) -> bool {
+            let Some(next) = next.as_any().downcast_ref::<Add>() else {
+                return false;
+            };
+            self.0 += next.0;
+            true
+        }
+    }
+
+    /// Like [`Add`], but never merges.
+    struct Step(i32);
+
+    impl EditorCommand for Step {
+        fn label(&self) -> String {
+            format!("Step {}", self.0)
+        }
+
+        fn size_bytes(&self) -> usize {
+            100
+        }
+
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            Add(self.0).execute(world)
+        }
+
+        fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+            Add(self.0).undo(world)
+        }
+    }
+
+    fn setup() -> (World, HistoryManager) {
+        let mut world = World::new();
+        world.init_resource::<Counter>();
+        (world, HistoryManager::new())
+    }
+
+    fn counter(world: &World) -> i32 {
+        world.resource::<Counter>().0
+    }
+
+    #[test]
+    fn group_is_one_step() {
+        let (mut world, mut history) = setup();
+        history.begin_group("Both");
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        history.end_group();
+
+        assert_eq!(history.undo_stack().len(), 1);
+        assert_eq!(history.undo_stack().next().unwrap().label(), "Both");
+        history.undo(&mut world).unwrap();
+        assert_eq!(counter(&world), 0);
+        history.redo(&mut world).unwrap();
+        assert_eq!(counter(&world), 3);
+    }
+
+    #[test]
+    fn nested_groups_reach_the_stack_once() {
+        let (mut world, mut history) = setup();
+        history.begin_group("Outer");
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.begin_group("Inner");
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        history.end_group();
+        history.end_group();
+
+        assert_eq!(history.undo_stack().len(), 1);
+        history.undo(&mut world).unwrap();
+        assert_eq!(counter(&world), 0);
+    }
+
+    #[test]
+    fn empty_group_is_discarded() {
+        let (_, mut history) = setup();
+        history.begin_group("Nothing");
+        history.end_group();
+        assert_eq!(history.undo_stack().len(), 0);
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn recent_commands_merge() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+        history.execute(Box::new(Add(2)), &mut world).unwrap();
+        assert_eq!(history.undo_stack().len(), 1);
+
+        history.undo(&mut world).unwrap();
+        assert_eq!(counter(&world), 0);
+    }
+
+    #[test]
+    fn no_merge_across_undo() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+        history.execute(Box::new(Step(5)), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+        history.execute(Box::new(Add(2)), &mut world).unwrap();
+
+        assert_eq!(history.undo_stack().len(), 2);
+        assert_eq!(history.redo_stack().len(), 0);
+        assert_eq!(counter(&world), 3);
+    }
+
+    #[test]
+    fn evicts_oldest_steps_beyond_max_steps() {
+        let (mut world, mut history) = setup();
+        history.max_steps = 2;
+        for amount in 1..=3 {
+            history.execute(Box::new(Step(amount)), &mut world).unwrap();
+        }
+
+        let labels: Vec<String> = history.undo_stack().map(|command| command.label()).collect();
+        assert_eq!(labels, ["Step 2", "Step 3"]);
+    }
+
+    #[test]
+    fn evicts_beyond_max_bytes_but_keeps_the_last_step() {
+        let (mut world, mut history) = setup();
+        history.max_bytes = 150;
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        assert_eq!(history.undo_stack().len(), 1);
+
+        history.max_bytes = 0;
+        history.execute(Box::new(Step(3)), &mut world).unwrap();
+        assert_eq!(history.undo_stack().len(), 1);
+    }
+
+    #[test]
+    fn save_point_follows_undo_and_redo() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.mark_saved();
+        assert!(!history.is_dirty());
+
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        assert!(history.is_dirty());
+        history.undo(&mut world).unwrap();
+        assert!(!history.is_dirty());
+        history.undo(&mut world).unwrap();
+        assert!(history.is_dirty());
+        history.redo(&mut world).unwrap();
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn save_point_is_lost_with_the_redo_stack() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.mark_saved();
+        history.undo(&mut world).unwrap();
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn merging_into_the_saved_step_makes_it_dirty() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+        history.mark_saved();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+
+        assert_eq!(history.undo_stack().len(), 1);
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn evicting_the_save_point_keeps_it_unreachable() {
+        let (mut world, mut history) = setup();
+        history.max_steps = 1;
+        history.mark_saved();
+        history.execute(Box::new(Step(1)), &mut world).unwrap();
+        history.execute(Box::new(Step(2)), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn moves_merge_only_when_changing_the_same_fields() {
+        let (_, mut history) = setup();
+        let entity = Entity::from_raw(1);
+        let mut push = |from: Transform, to: Transform| {
+            history.push(Box::new(TransformChange {
+                targets: vec![TransformTarget { entity, from, to }],
+            }));
+            history.undo_stack().len()
+        };
+
+        assert_eq!(push(Transform::IDENTITY, Transform::from_xyz(1.0, 0.0, 0.0)), 1);
+        assert_eq!(push(Transform::from_xyz(1.0, 0.0, 0.0), Transform::from_xyz(2.0, 0.0, 0.0)), 1);
+        let scaled = Transform::from_xyz(2.0, 0.0, 0.0).with_scale(Vec3::splat(2.0));
+        assert_eq!(push(Transform::from_xyz(2.0, 0.0, 0.0), scaled), 2);
+    }
+
+    #[test]
+    fn break_merge_starts_a_new_step() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+        history.break_merge();
+        history.execute(Box::new(Add(2)), &mut world).unwrap();
+        assert_eq!(history.undo_stack().len(), 2);
+    }
+
+    #[test]
+    fn restore_drops_unsent_events() {
+        let (mut world, mut history) = setup();
+        history.execute(Box::new(Add(1)), &mut world).unwrap();
+        let type_registry = TypeRegistry::default();
+        let record = HistoryManager::new().to_record(&type_registry);
+        history.restore(record, &type_registry, &EntityHashMap::default());
+
+        assert!(history.events.is_empty());
+        assert_eq!(history.undo_stack().len(), 0);
+    }
+
+    #[test]
+    fn set_components_inserts_and_removes_on_undo() {
+        let (mut world, mut history) = setup();
+        world.init_resource::<AppTypeRegistry>();
+        world.resource::<AppTypeRegistry>().write().register::<Name>();
+        let renamed = world.spawn(Name::new("Before")).id();
+        let named = world.spawn_empty().id();
+
+        let command = SetComponents {
+            label: "Set".to_string(),
+            changes: vec![
+                ComponentChange {
+                    entity: renamed,
+                    type_id: TypeId::of::<Name>(),
+                    before: Some(Box::new(Name::new("Before"))),
+                    after: Some(Box::new(Name::new("After"))),
+                },
+                ComponentChange {
+                    entity: named,
+                    type_id: TypeId::of::<Name>(),
+                    before: None,
+                    after: Some(Box::new(Name::new("New"))),
+                },
+            ],
+        };
+        history.execute(Box::new(command), &mut world).unwrap();
+        assert_eq!(world.get::<Name>(renamed).unwrap().as_str(), "After");
+        assert_eq!(world.get::<Name>(named).unwrap().as_str(), "New");
+
+        history.undo(&mut world).unwrap();
+        assert_eq!(world.get::<Name>(renamed).unwrap().as_str(), "Before");
+        assert!(world.get::<Name>(named).is_none());
+    }
+}
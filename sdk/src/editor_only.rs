@@ -0,0 +1,11 @@
+use bevy::prelude::{Component, ReflectComponent, ReflectDefault};
+use bevy_reflect::Reflect;
+
+/// Marks editor tooling such as cameras, gizmo helpers and overlays, as opposed to scene content.
+///
+/// These entities are hidden from the Hierarchy tab, ignored by picking and never saved.
+/// Editor components should `#[require(EditorOnly)]` so their entities are marked automatically;
+/// components from other crates get it through `App::register_required_components` in `main`.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Component, Default)]
+pub struct EditorOnly;
@@ -0,0 +1,747 @@
+use std::any::TypeId;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::log::warn;
+use bevy::prelude::{
+    AppTypeRegistry, BuildChildren, BuildChildrenTransformExt, Children, DespawnRecursiveExt,
+    DynamicScene, DynamicSceneBuilder, Entity, Name, Parent, Transform, World,
+};
+use bevy::reflect::{PartialReflect, TypeRegistry};
+use bevy::scene::DynamicEntity;
+use crate::command_record::{
+    map_entity, reflect_size, serialize_value, CommandRecord, SnapshotRecord,
+};
+use crate::editor_commands::{
+    require_component, with_descendants, CommandError, EditorCommand, HistoryManager,
+    RestoredEntities,
+};
+use crate::scene_file::deserialize_scene;
+use crate::selection::EditorSelection;
+
+pub(crate) fn is_parent(component: &dyn PartialReflect) -> bool {
+    component
+        .get_represented_type_info()
+        .is_some_and(|info| info.type_id() == TypeId::of::<Parent>())
+}
+
+/// Reflected copy of an entity and its descendants, restorable after a despawn.
+///
+/// Components that aren't registered with `ReflectComponent` are not captured.
+pub struct EntitySnapshot {
+    scene: DynamicScene,
+    root: Entity,
+    parent: Option<Entity>,
+    sibling_index: usize,
+    /// The ids the history knows the scene's entities by, where they differ from the scene's own.
+    /// Only snapshots read back from a [`SnapshotRecord`] have any.
+    keys: EntityHashMap<Entity>,
+    /// Serialized length of the components, see [`reflect_size`].
+    size: usize,
+}
+
+impl EntitySnapshot {
+    pub fn capture(world: &World, root: Entity) -> Result<Self, CommandError> {
+        world
+            .get_entity(root)
+            .map_err(|_| CommandError::EntityNotFound(root))?;
+
+        let parent = world.get::<Parent>(root).map(Parent::get);
+        let sibling_index = parent
+            .and_then(|parent| world.get::<Children>(parent))
+            .and_then(|children| children.iter().position(|child| *child == root))
+            .unwrap_or(0);
+
+        let mut scene = DynamicSceneBuilder::from_world(world)
+            .extract_entities(with_descendants(world, root).into_iter())
+            .build();
+        // The parent is kept aside, it may be respawned under another id before the restore.
+        for entity in &mut scene.entities {
+            if entity.entity == root {
+                entity.components.retain(|component| !is_parent(component.as_ref()));
+            }
+        }
+
+        let type_registry = world.resource::<AppTypeRegistry>().read();
+        let size = scene_size(&scene, &type_registry);
+        Ok(Self {
+            scene,
+            root,
+            parent,
+            sibling_index,
+            keys: EntityHashMap::default(),
+            size,
+        })
+    }
+
+    /// Reads a snapshot saved with the history, mapping saved ids through `entity_map`.
+    pub fn from_record(
+        record: SnapshotRecord,
+        type_registry: &TypeRegistry,
+        entity_map: &EntityHashMap<Entity>,
+    ) -> Option<Self> {
+        let scene = deserialize_scene(&record.scene, type_registry).ok()?;
+        let keys = record
+            .entities
+            .iter()
+            .map(|(scene_entity, entity)| {
+                Some((Entity::try_from_bits(*scene_entity).ok()?, map_entity(*entity, entity_map)?))
+            })
+            .collect::<Option<_>>()?;
+        let parent = match record.parent {
+            Some(parent) => Some(map_entity(parent, entity_map)?),
+            None => None,
+        };
+        let size = scene_size(&scene, type_registry);
+        Some(Self {
+            scene,
+            root: Entity::try_from_bits(record.root).ok()?,
+            parent,
+            sibling_index: record.sibling_index,
+            keys,
+            size,
+        })
+    }
+
+    /// Serializable form for [`EditorCommand::record`]. Components that can't be serialized,
+    /// e.g. ones holding asset handles, are left out like they are when saving the scene.
+    pub fn record(&self, type_registry: &TypeRegistry) -> Option<SnapshotRecord> {
+        let scene = DynamicScene {
+            resources: Vec::new(),
+            entities: self
+                .scene
+                .entities
+                .iter()
+                .map(|entity| DynamicEntity {
+                    entity: entity.entity,
+                    components: entity
+                        .components
+                        .iter()
+                        .filter(|component| serialize_value(component.as_ref(), type_registry).is_some())
+                        .map(|component| component.clone_value())
+                        .collect(),
+                })
+                .collect(),
+        };
+        Some(SnapshotRecord {
+            scene: scene.serialize(type_registry).ok()?,
+            entities: self
+                .scene
+                .entities
+                .iter()
+                .map(|entity| (entity.entity.to_bits(), self.key(entity.entity).to_bits()))
+                .collect(),
+            root: self.root.to_bits(),
+            parent: self.parent.map(Entity::to_bits),
+            sibling_index: self.sibling_index,
+        })
+    }
+
+    fn key(&self, scene_entity: Entity) -> Entity {
+        self.keys.get(&scene_entity).copied().unwrap_or(scene_entity)
+    }
+
+    /// The root as the history knows it.
+    pub fn root(&self) -> Entity {
+        self.key(self.root)
+    }
+
+    /// Spawns the snapshot back into the world as new entities and returns the restored root.
+    ///
+    /// The old ids are reported through [`RestoredEntities`], so the history can point the
+    /// commands that reference them at the new entities.
+    pub fn restore(&self, world: &mut World) -> Result<Entity, CommandError> {
+        let mut entity_map = EntityHashMap::default();
+        self.scene
+            .write_to_world(world, &mut entity_map)
+            .map_err(|error| CommandError::Scene(error.to_string()))?;
+
+        let root = entity_map[&self.root];
+        if let Some(parent) = self.parent {
+            if let Ok(mut parent) = world.get_entity_mut(parent) {
+                let index = parent
+                    .get::<Children>()
+                    .map_or(0, |children| self.sibling_index.min(children.len()));
+                parent.insert_children(index, &[root]);
+            }
+        }
+        let restored = entity_map
+            .into_iter()
+            .map(|(scene_entity, entity)| (self.key(scene_entity), entity));
+        world.get_resource_or_init::<RestoredEntities>().0.extend(restored);
+        Ok(root)
+    }
+
+    /// The parent is the only entity outside the snapshot it refers to.
+    pub fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        self.parent = self.parent.map(|parent| entity_map.get(&parent).copied().unwrap_or(parent));
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size
+    }
+
+    pub fn despawn(&self, world: &mut World) {
+        if let Ok(entity) = world.get_entity_mut(self.root) {
+            entity.despawn_recursive();
+        }
+    }
+}
+
+fn scene_size(scene: &DynamicScene, type_registry: &TypeRegistry) -> usize {
+    scene
+        .entities
+        .iter()
+        .flat_map(|entity| &entity.components)
+        .map(|component| reflect_size(component.as_ref(), type_registry))
+        .sum()
+}
+
+/// Spawns an entity hierarchy. Undo despawns it, redo restores the exact snapshot taken on undo.
+pub struct SpawnEntity {
+    spawn: Option<Box<dyn FnOnce(&mut World) -> Entity + Send + Sync>>,
+    entity: Option<Entity>,
+    snapshot: Option<EntitySnapshot>,
+}
+
+impl SpawnEntity {
+    pub fn new(spawn: impl FnOnce(&mut World) -> Entity + Send + Sync + 'static) -> Self {
+        Self {
+            spawn: Some(Box::new(spawn)),
+            entity: None,
+            snapshot: None,
+        }
+    }
+
+    /// Records an entity hierarchy that has already been spawned, for [`HistoryManager::push`].
+    pub fn spawned(entity: Entity) -> Self {
+        Self {
+            spawn: None,
+            entity: Some(entity),
+            snapshot: None,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: EntitySnapshot) -> Self {
+        Self {
+            spawn: None,
+            entity: None,
+            snapshot: Some(snapshot),
+        }
+    }
+
+    /// The spawned root, once the command has been executed.
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+}
+
+impl EditorCommand for SpawnEntity {
+    fn label(&self) -> String {
+        match self.entity {
+            Some(entity) => format!("Spawn {entity}"),
+            None => "Spawn entity".to_string(),
+        }
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        self.entity.into_iter().collect()
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.snapshot.as_ref().map_or(0, EntitySnapshot::size_bytes)
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::Spawn {
+            entity: self.entity.map(Entity::to_bits),
+            snapshot: match &self.snapshot {
+                Some(snapshot) => Some(snapshot.record(type_registry)?),
+                None => None,
+            },
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        if let Some(spawn) = self.spawn.take() {
+            self.entity = Some(spawn(world));
+        } else if let Some(snapshot) = &self.snapshot {
+            self.entity = Some(snapshot.restore(world)?);
+            self.snapshot = None;
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let Some(entity) = self.entity.take() else {
+            return Ok(());
+        };
+        let snapshot = EntitySnapshot::capture(world, entity)?;
+        snapshot.despawn(world);
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        self.entity = self.entity.map(|entity| entity_map.get(&entity).copied().unwrap_or(entity));
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.map_entities(entity_map);
+        }
+    }
+}
+
+/// Recursively despawns an entity, keeping a snapshot so undo restores the whole hierarchy.
+pub struct DespawnEntity {
+    entity: Entity,
+    snapshot: Option<EntitySnapshot>,
+}
+
+impl DespawnEntity {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            snapshot: None,
+        }
+    }
+
+    /// A deletion that has already happened, undone by restoring `snapshot`.
+    pub fn despawned(snapshot: EntitySnapshot) -> Self {
+        Self {
+            entity: snapshot.root(),
+            snapshot: Some(snapshot),
+        }
+    }
+}
+
+impl EditorCommand for DespawnEntity {
+    fn label(&self) -> String {
+        format!("Delete {}", self.entity)
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        vec![self.entity]
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.snapshot.as_ref().map_or(0, EntitySnapshot::size_bytes)
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::Despawn {
+            entity: self.entity.to_bits(),
+            snapshot: match &self.snapshot {
+                Some(snapshot) => Some(snapshot.record(type_registry)?),
+                None => None,
+            },
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let snapshot = EntitySnapshot::capture(world, self.entity)?;
+        snapshot.despawn(world);
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        if let Some(snapshot) = self.snapshot.take() {
+            self.entity = snapshot.restore(world)?;
+        }
+        Ok(())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        self.entity = entity_map.get(&self.entity).copied().unwrap_or(self.entity);
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.map_entities(entity_map);
+        }
+    }
+}
+
+/// Moves an entity to a new parent (or to the root with `None`), keeping its world position.
+pub struct ReparentEntity {
+    pub entity: Entity,
+    pub to: Option<Entity>,
+    pub from: Option<Entity>,
+    pub from_index: usize,
+    pub from_transform: Transform,
+    pub to_transform: Option<Transform>,
+}
+
+impl ReparentEntity {
+    pub fn new(world: &World, entity: Entity, to: Option<Entity>) -> Result<Self, CommandError> {
+        check_cycle(world, entity, to)?;
+        let from_transform = *world
+            .get_entity(entity)
+            .map_err(|_| CommandError::EntityNotFound(entity))?
+            .get::<Transform>()
+            .ok_or(CommandError::MissingComponent {
+                entity,
+                component: std::any::type_name::<Transform>(),
+            })?;
+        let from = world.get::<Parent>(entity).map(Parent::get);
+        let from_index = from
+            .and_then(|parent| world.get::<Children>(parent))
+            .and_then(|children| children.iter().position(|child| *child == entity))
+            .unwrap_or(0);
+
+        Ok(Self {
+            entity,
+            to,
+            from,
+            from_index,
+            from_transform,
+            to_transform: None,
+        })
+    }
+}
+
+/// Returns an error if `parent` is `entity` itself or one of its descendants.
+fn check_cycle(world: &World, entity: Entity, parent: Option<Entity>) -> Result<(), CommandError> {
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+    let mut ancestor = Some(parent);
+    while let Some(current) = ancestor {
+        if current == entity {
+            return Err(CommandError::HierarchyCycle { entity, parent });
+        }
+        ancestor = world.get::<Parent>(current).map(Parent::get);
+    }
+    Ok(())
+}
+
+fn set_parent(
+    world: &mut World,
+    entity: Entity,
+    parent: Option<Entity>,
+    index: Option<usize>,
+) -> Result<(), CommandError> {
+    world
+        .get_entity(entity)
+        .map_err(|_| CommandError::EntityNotFound(entity))?;
+    if let Some(parent) = parent {
+        world
+            .get_entity(parent)
+            .map_err(|_| CommandError::EntityNotFound(parent))?;
+    }
+    check_cycle(world, entity, parent)?;
+
+    match parent {
+        Some(parent) => {
+            world.entity_mut(entity).set_parent_in_place(parent);
+            if let Some(index) = index {
+                let mut parent = world.entity_mut(parent);
+                let index = parent
+                    .get::<Children>()
+                    .map_or(0, |children| index.min(children.len() - 1));
+                parent.insert_children(index, &[entity]);
+            }
+        }
+        None => {
+            world.entity_mut(entity).remove_parent_in_place();
+        }
+    }
+    Ok(())
+}
+
+impl EditorCommand for ReparentEntity {
+    fn label(&self) -> String {
+        match self.to {
+            Some(parent) => format!("Reparent {} to {parent}", self.entity),
+            None => format!("Unparent {}", self.entity),
+        }
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        vec![self.entity]
+    }
+
+    fn record(&self, _type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::Reparent {
+            entity: self.entity.to_bits(),
+            to: self.to.map(Entity::to_bits),
+            from: self.from.map(Entity::to_bits),
+            from_index: self.from_index,
+            from_transform: self.from_transform,
+            to_transform: self.to_transform,
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        require_component::<Transform>(world, self.entity)?;
+        set_parent(world, self.entity, self.to, None)?;
+        match self.to_transform {
+            // Redo: reuse the exact transform from the first run instead of recomputing it.
+            Some(transform) => {
+                if let Some(mut current) = world.get_mut::<Transform>(self.entity) {
+                    *current = transform;
+                }
+            }
+            None => self.to_transform = world.get::<Transform>(self.entity).copied(),
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        set_parent(world, self.entity, self.from, Some(self.from_index))?;
+        if let Some(mut transform) = world.get_mut::<Transform>(self.entity) {
+            *transform = self.from_transform;
+        }
+        Ok(())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        let map = |entity: Entity| entity_map.get(&entity).copied().unwrap_or(entity);
+        self.entity = map(self.entity);
+        self.to = self.to.map(map);
+        self.from = self.from.map(map);
+    }
+}
+
+/// The selected entities without those that have a selected ancestor,
+/// for operations that act on whole hierarchies.
+pub fn selection_roots(world: &World) -> Vec<Entity> {
+    roots(world.resource::<EditorSelection>().as_slice(), |entity| {
+        world.get::<Parent>(entity).map(Parent::get)
+    })
+}
+
+/// `entities` without those that have an ancestor among them, with `parent` looking up parents.
+/// For systems that see the hierarchy through a `Query` rather than the `World`.
+pub fn roots(entities: &[Entity], parent: impl Fn(Entity) -> Option<Entity>) -> Vec<Entity> {
+    entities
+        .iter()
+        .copied()
+        .filter(|entity| {
+            let mut ancestor = parent(*entity);
+            while let Some(next) = ancestor {
+                if entities.contains(&next) {
+                    return false;
+                }
+                ancestor = parent(next);
+            }
+            true
+        })
+        .collect()
+}
+
+/// Despawns the selected entities as one undo step. Selected descendants of
+/// another selected entity are skipped, they go with their ancestor.
+pub fn delete_selected(world: &mut World) {
+    let roots = selection_roots(world);
+    if roots.is_empty() {
+        return;
+    }
+
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        history.begin_group("Delete selection");
+        for entity in roots {
+            if let Err(error) = history.execute(Box::new(DespawnEntity::new(entity)), world) {
+                warn!("Failed to delete {entity}: {error}");
+            }
+        }
+        history.end_group();
+    });
+    world.resource_mut::<EditorSelection>().clear();
+}
+
+/// A change picked from the Hierarchy tab's context menu, applied by [`apply_hierarchy_edits`]
+/// once the hierarchy has been drawn.
+pub enum HierarchyEdit {
+    /// Moves the entity under a new parent, or to the root for `None`.
+    Reparent(Entity, Option<Entity>),
+    /// Spawns an empty child under the entity.
+    AddChild(Entity),
+}
+
+/// Context menu of a row in the Hierarchy tab: adds an empty child, parents the selection to
+/// the row's entity, or moves the entity to the root.
+pub fn hierarchy_context_menu(
+    ui: &mut egui::Ui,
+    entity: Entity,
+    world: &World,
+    selection_roots: &[Entity],
+    edits: &mut Vec<HierarchyEdit>,
+) {
+    if ui.button("Add child entity").clicked() {
+        edits.push(HierarchyEdit::AddChild(entity));
+        ui.close_menu();
+    }
+    let others: Vec<Entity> = selection_roots
+        .iter()
+        .copied()
+        .filter(|root| *root != entity)
+        .collect();
+    if ui
+        .add_enabled(!others.is_empty(), egui::Button::new("Parent selection here"))
+        .clicked()
+    {
+        edits.extend(others.into_iter().map(|root| HierarchyEdit::Reparent(root, Some(entity))));
+        ui.close_menu();
+    }
+    let has_parent = world.get::<Parent>(entity).is_some();
+    if ui.add_enabled(has_parent, egui::Button::new("Move to root")).clicked() {
+        edits.push(HierarchyEdit::Reparent(entity, None));
+        ui.close_menu();
+    }
+}
+
+pub fn apply_hierarchy_edits(world: &mut World, edits: Vec<HierarchyEdit>) {
+    let mut reparent = Vec::new();
+    for edit in edits {
+        match edit {
+            HierarchyEdit::Reparent(entity, to) => reparent.push((entity, to)),
+            HierarchyEdit::AddChild(parent) => add_child(world, parent),
+        }
+    }
+    reparent_entities(world, reparent);
+}
+
+/// Spawns an empty, named entity under `parent` as one undo step.
+fn add_child(world: &mut World, parent: Entity) {
+    let spawn = SpawnEntity::new(move |world: &mut World| {
+        world
+            .spawn((Name::new("Entity"), Transform::default()))
+            .set_parent(parent)
+            .id()
+    });
+    let result = world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        history.execute(Box::new(spawn), world)
+    });
+    if let Err(error) = result {
+        warn!("Failed to add a child to {parent}: {error}");
+    }
+}
+
+/// Reparents entities as one undo step, keeping their world positions.
+/// Requests that would create a cycle are skipped with a warning.
+pub fn reparent_entities(world: &mut World, requests: Vec<(Entity, Option<Entity>)>) {
+    if requests.is_empty() {
+        return;
+    }
+
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        history.begin_group("Reparent");
+        for (entity, to) in requests {
+            let result = ReparentEntity::new(world, entity, to)
+                .and_then(|command| history.execute(Box::new(command), world));
+            if let Err(error) = result {
+                warn!("Failed to reparent {entity}: {error}");
+            }
+        }
+        history.end_group();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{AppTypeRegistry, Vec3, With};
+    use crate::editor_commands::TransformChange;
+    use super::*;
+
+    fn setup() -> (World, HistoryManager) {
+        let mut world = World::new();
+        let type_registry = AppTypeRegistry::default();
+        {
+            let mut type_registry = type_registry.write();
+            type_registry.register::<Transform>();
+            type_registry.register::<Parent>();
+            type_registry.register::<Children>();
+        }
+        world.insert_resource(type_registry);
+        (world, HistoryManager::new())
+    }
+
+    fn moved() -> Transform {
+        Transform::from_translation(Vec3::X)
+    }
+
+    #[test]
+    fn undoing_a_delete_respawns_under_a_new_id_and_remaps_the_history() {
+        let (mut world, mut history) = setup();
+        let entity = world.spawn(Transform::default()).id();
+        let change = TransformChange::capture(&world, [(entity, moved())]).unwrap();
+        history.execute(Box::new(change), &mut world).unwrap();
+        history.execute(Box::new(DespawnEntity::new(entity)), &mut world).unwrap();
+
+        history.undo(&mut world).unwrap();
+        let restored = world
+            .query_filtered::<Entity, With<Transform>>()
+            .single(&world);
+        assert_ne!(restored, entity);
+        assert_eq!(*world.get::<Transform>(restored).unwrap(), moved());
+
+        // The move was recorded against the old id and now applies to the restored entity.
+        history.undo(&mut world).unwrap();
+        assert_eq!(*world.get::<Transform>(restored).unwrap(), Transform::default());
+        history.redo(&mut world).unwrap();
+        history.redo(&mut world).unwrap();
+        assert!(world.get_entity(restored).is_err());
+    }
+
+    #[test]
+    fn restore_reattaches_to_the_parent_at_its_old_index() {
+        let (mut world, mut history) = setup();
+        let parent = world.spawn(Transform::default()).id();
+        let first = world.spawn(Transform::default()).id();
+        let second = world.spawn(Transform::default()).id();
+        world.entity_mut(parent).add_children(&[first, second]);
+
+        history.execute(Box::new(DespawnEntity::new(first)), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+        let children = world.get::<Children>(parent).unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[1], second);
+        assert_eq!(world.get::<Parent>(children[0]).map(Parent::get), Some(parent));
+    }
+
+    #[test]
+    fn adding_a_child_is_one_undoable_step() {
+        let (mut world, history) = setup();
+        world.insert_resource(history);
+        let parent = world.spawn(Transform::default()).id();
+
+        apply_hierarchy_edits(&mut world, vec![HierarchyEdit::AddChild(parent)]);
+        let child = world.get::<Children>(parent).unwrap()[0];
+        assert_eq!(world.get::<Name>(child).map(Name::as_str), Some("Entity"));
+
+        world.resource_scope::<HistoryManager, _>(|world, mut history| {
+            assert_eq!(history.undo_stack().len(), 1);
+            history.undo(world).unwrap();
+        });
+        assert!(world.get_entity(child).is_err());
+        assert!(world.get::<Children>(parent).is_none_or(|children| children.is_empty()));
+    }
+
+    #[test]
+    fn reparenting_under_itself_or_a_descendant_fails() {
+        let (mut world, _) = setup();
+        let parent = world.spawn(Transform::default()).id();
+        let child = world.spawn(Transform::default()).set_parent(parent).id();
+
+        assert!(matches!(
+            ReparentEntity::new(&world, parent, Some(parent)),
+            Err(CommandError::HierarchyCycle { .. })
+        ));
+        assert!(matches!(
+            ReparentEntity::new(&world, parent, Some(child)),
+            Err(CommandError::HierarchyCycle { .. })
+        ));
+        assert!(ReparentEntity::new(&world, child, None).is_ok());
+    }
+
+    #[test]
+    fn redoing_a_reparent_without_transform_fails_instead_of_panicking() {
+        let (mut world, mut history) = setup();
+        let parent = world.spawn(Transform::default()).id();
+        let entity = world.spawn(Transform::default()).id();
+        let reparent = ReparentEntity::new(&world, entity, Some(parent)).unwrap();
+        history.execute(Box::new(reparent), &mut world).unwrap();
+        history.undo(&mut world).unwrap();
+
+        world.entity_mut(entity).remove::<Transform>();
+        assert!(matches!(
+            history.redo(&mut world),
+            Err(CommandError::MissingComponent { .. })
+        ));
+    }
+}
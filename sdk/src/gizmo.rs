@@ -1,25 +1,446 @@
-use bevy::prelude::{Changed, Commands, Entity, GlobalTransform, Query, ResMut, Transform, With, World};
-use bevy_inspector_egui::bevy_inspector::hierarchy::{SelectedEntities, SelectionMode};
-use bevy_render::camera::Projection;
+use bevy::input::ButtonInput;
+use bevy::log::warn;
+use bevy::prelude::{
+    Entity, EulerRot, GlobalTransform, KeyCode, Local, Parent, Quat, Query, Res, ResMut, Resource,
+    Transform, Vec3, World,
+};
+use transform_gizmo_bevy::prelude::{GizmoMode, GizmoOptions, GizmoOrientation, TransformPivotPoint};
 use transform_gizmo_bevy::GizmoTarget;
-use crate::{GizmoMode, MainCamera, UiState};
+use crate::editor_commands::{egui_wants_keyboard, HistoryManager, TransformChange, TransformTarget};
+use crate::entity_commands::{roots, selection_roots};
+use crate::keymap::{EditorAction, EditorKeymap};
+use crate::selection::EditorSelection;
 
-pub fn draw_gizmo(
-    mut commands: Commands,
-    mut ui_state: ResMut<UiState>,
-    query: Query<(Entity, Option<&mut GizmoTarget>)>,
+/// What dragging the gizmo does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GizmoTool {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+    /// Translate, rotate and scale handles at once.
+    Universal,
+}
+
+/// Whether the gizmo's axes follow the world or the selected entity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GizmoSpace {
+    #[default]
+    World,
+    Local,
+}
+
+impl GizmoSpace {
+    fn toggled(self) -> Self {
+        match self {
+            Self::World => Self::Local,
+            Self::Local => Self::World,
+        }
+    }
+}
+
+/// What a multi-selection rotates and scales around.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GizmoPivot {
+    /// Every entity around its own origin.
+    IndividualOrigins,
+    #[default]
+    SelectionCenter,
+    /// The entity selected last. Only it gets a `GizmoTarget`, the others follow it.
+    LastSelected,
+}
+
+impl GizmoPivot {
+    const ALL: [Self; 3] = [Self::IndividualOrigins, Self::SelectionCenter, Self::LastSelected];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::IndividualOrigins => "Individual origins",
+            Self::SelectionCenter => "Selection center",
+            Self::LastSelected => "Last selected",
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|pivot| *pivot == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Increments the gizmo snaps to, also used by [`snap_selection_to_grid`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapSteps {
+    /// Grid size in world units.
+    pub translation: f32,
+    pub rotation_degrees: f32,
+    pub scale: f32,
+}
+
+impl Default for SnapSteps {
+    fn default() -> Self {
+        Self {
+            translation: 0.25,
+            rotation_degrees: 15.0,
+            scale: 0.1,
+        }
+    }
+}
+
+/// Gizmo settings chosen in the viewport toolbar or with the keymap, applied to `GizmoOptions`.
+#[derive(Resource, Clone, Copy, Default, PartialEq)]
+pub struct GizmoSettings {
+    pub tool: GizmoTool,
+    pub space: GizmoSpace,
+    pub pivot: GizmoPivot,
+    /// Holding [`EditorAction::GizmoSnap`] inverts this for the drag.
+    /// Also snaps Transform edits in the inspector, see [`snap_transform_edit`].
+    pub snapping: bool,
+    pub snap: SnapSteps,
+}
+
+impl GizmoSettings {
+    /// The entities that get a `GizmoTarget` for the current selection.
+    pub fn targets<'a>(&self, selection: &'a EditorSelection) -> &'a [Entity] {
+        match self.pivot {
+            GizmoPivot::LastSelected => {
+                let selected = selection.as_slice();
+                &selected[selected.len().saturating_sub(1)..]
+            }
+            _ => selection.as_slice(),
+        }
+    }
+}
+
+pub fn apply_gizmo_settings(
+    settings: Res<GizmoSettings>,
+    keymap: Res<EditorKeymap>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut options: ResMut<GizmoOptions>,
+) {
+    options.gizmo_modes = match settings.tool {
+        GizmoTool::Translate => GizmoMode::all_translate(),
+        GizmoTool::Rotate => GizmoMode::all_rotate(),
+        GizmoTool::Scale => GizmoMode::all_scale(),
+        GizmoTool::Universal => GizmoMode::all(),
+    };
+    options.gizmo_orientation = match settings.space {
+        GizmoSpace::World => GizmoOrientation::Global,
+        GizmoSpace::Local => GizmoOrientation::Local,
+    };
+    options.pivot_point = match settings.pivot {
+        GizmoPivot::IndividualOrigins => TransformPivotPoint::IndividualOrigins,
+        GizmoPivot::SelectionCenter | GizmoPivot::LastSelected => TransformPivotPoint::MedianPoint,
+    };
+    // One gizmo for the whole selection rather than one per entity.
+    options.group_targets = true;
+
+    let inverted = keymap.pressed(EditorAction::GizmoSnap, &keyboard_input);
+    options.snapping = settings.snapping != inverted;
+    options.snap_distance = settings.snap.translation;
+    options.snap_angle = settings.snap.rotation_degrees.to_radians();
+    options.snap_scale = settings.snap.scale;
+}
+
+/// Switches tool, space and pivot from the keymap, unless a text field has focus.
+pub fn gizmo_shortcuts(world: &mut World) {
+    if egui_wants_keyboard(world) {
+        return;
+    }
+
+    let keyboard_input = world.resource::<ButtonInput<KeyCode>>();
+    let keymap = world.resource::<EditorKeymap>();
+    let pressed = |action| keymap.just_pressed(action, keyboard_input);
+    let tool = if pressed(EditorAction::GizmoTranslate) {
+        Some(GizmoTool::Translate)
+    } else if pressed(EditorAction::GizmoRotate) {
+        Some(GizmoTool::Rotate)
+    } else if pressed(EditorAction::GizmoScale) {
+        Some(GizmoTool::Scale)
+    } else if pressed(EditorAction::GizmoUniversal) {
+        Some(GizmoTool::Universal)
+    } else {
+        None
+    };
+    let toggle_space = pressed(EditorAction::GizmoToggleSpace);
+    let cycle_pivot = pressed(EditorAction::GizmoCyclePivot);
+    let snap_to_grid = pressed(EditorAction::SnapToGrid);
+
+    if snap_to_grid {
+        snap_selection_to_grid(world);
+    }
+    if tool.is_none() && !toggle_space && !cycle_pivot {
+        return;
+    }
+    let mut settings = world.resource_mut::<GizmoSettings>();
+    if let Some(tool) = tool {
+        settings.tool = tool;
+    }
+    if toggle_space {
+        settings.space = settings.space.toggled();
+    }
+    if cycle_pivot {
+        settings.pivot = settings.pivot.next();
+    }
+}
+
+/// Tool, space, pivot and snapping controls drawn over the viewport.
+pub fn gizmo_toolbar_ui(ui: &mut egui::Ui, world: &mut World) {
+    // Edits a copy so `GizmoSettings` is only marked changed when something was clicked.
+    let mut settings = *world.resource::<GizmoSettings>();
+    let mut snap_to_grid = false;
+    ui.horizontal(|ui| {
+        for (tool, label) in [
+            (GizmoTool::Translate, "Move"),
+            (GizmoTool::Rotate, "Rotate"),
+            (GizmoTool::Scale, "Scale"),
+            (GizmoTool::Universal, "All"),
+        ] {
+            ui.selectable_value(&mut settings.tool, tool, label);
+        }
+        ui.separator();
+        ui.selectable_value(&mut settings.space, GizmoSpace::World, "World");
+        ui.selectable_value(&mut settings.space, GizmoSpace::Local, "Local");
+        ui.separator();
+        egui::ComboBox::from_id_salt("gizmo_pivot")
+            .selected_text(settings.pivot.label())
+            .show_ui(ui, |ui| {
+                for pivot in GizmoPivot::ALL {
+                    ui.selectable_value(&mut settings.pivot, pivot, pivot.label());
+                }
+            });
+        ui.separator();
+        ui.toggle_value(&mut settings.snapping, "Snap");
+        let snap = &mut settings.snap;
+        ui.add(egui::DragValue::new(&mut snap.translation).speed(0.01).range(0.01..=100.0).suffix(" m"));
+        ui.add(egui::DragValue::new(&mut snap.rotation_degrees).speed(1.0).range(1.0..=180.0).suffix("°"));
+        ui.add(egui::DragValue::new(&mut snap.scale).speed(0.01).range(0.01..=10.0).prefix("×"));
+        snap_to_grid = ui.button("Snap to grid").on_hover_text("Move the selection onto the grid").clicked();
+    });
+    if settings != *world.resource::<GizmoSettings>() {
+        world.insert_resource(settings);
+    }
+    if snap_to_grid {
+        snap_selection_to_grid(world);
+    }
+}
+
+/// Moves the world position of every selected entity onto the grid, as one undo step.
+/// Descendants of selected entities are left alone, they move with their ancestor.
+pub fn snap_selection_to_grid(world: &mut World) {
+    let step = world.resource::<GizmoSettings>().snap.translation;
+    let snapped: Vec<(Entity, Transform)> = selection_roots(world)
+        .into_iter()
+        .filter_map(|entity| {
+            let transform = world.get::<Transform>(entity)?;
+            // The grid is in world space, a child of a moved or rotated parent snaps to it too.
+            let parent = world
+                .get::<Parent>(entity)
+                .and_then(|parent| world.get::<GlobalTransform>(parent.get()))
+                .copied()
+                .unwrap_or_default();
+            let global = parent * *transform;
+            let translation = (global.translation() / step).round() * step;
+            let local = parent.affine().inverse().transform_point3(translation);
+            (local.distance(transform.translation) > 1e-5)
+                .then_some((entity, transform.with_translation(local)))
+        })
+        .collect();
+    if snapped.is_empty() {
+        return;
+    }
+
+    let result = TransformChange::capture(world, snapped).and_then(|command| {
+        world.resource_scope::<HistoryManager, _>(|world, mut history| {
+            history.execute(Box::new(command), world)
+        })
+    });
+    if let Err(error) = result {
+        warn!("Failed to snap the selection to the grid: {error}");
+    }
+}
+
+/// Rounds the parts of an inspector edit that differ from `before` to the snap steps, so typed
+/// and dragged numbers land on the grid like gizmo drags do. Untouched values stay as they are.
+/// Rotations are snapped per Euler angle, in the XYZ order the inspector shows them in.
+pub fn snap_transform_edit(steps: &SnapSteps, before: &Transform, transform: &mut Transform) {
+    let round = |value: f32, step: f32| (value / step).round() * step;
+    let changed = |a: f32, b: f32| (a - b).abs() > 1e-5;
+
+    for axis in 0..3 {
+        if changed(transform.translation[axis], before.translation[axis]) {
+            transform.translation[axis] = round(transform.translation[axis], steps.translation);
+        }
+        if changed(transform.scale[axis], before.scale[axis]) {
+            transform.scale[axis] = round(transform.scale[axis], steps.scale);
+        }
+    }
+
+    if transform.rotation != before.rotation {
+        let step = steps.rotation_degrees.to_radians();
+        let (x, y, z) = transform.rotation.to_euler(EulerRot::XYZ);
+        let (bx, by, bz) = before.rotation.to_euler(EulerRot::XYZ);
+        let snap = |angle: f32, before: f32| if changed(angle, before) { round(angle, step) } else { angle };
+        transform.rotation = Quat::from_euler(EulerRot::XYZ, snap(x, bx), snap(y, by), snap(z, bz));
+    }
+}
+
+/// With [`GizmoPivot::LastSelected`], moves the rest of the selection along with the
+/// gizmo target, around the target's origin.
+///
+/// Works in world space, as the followers may have other parents than the target. Only the
+/// hierarchy roots follow, and none of the target's ancestors or descendants, which already
+/// move with it.
+pub fn follow_last_selected(
+    settings: Res<GizmoSettings>,
+    selection: Res<EditorSelection>,
+    targets: Query<&GizmoTarget>,
+    parents: Query<&Parent>,
+    globals: Query<&GlobalTransform>,
+    mut transforms: Query<&mut Transform>,
+    mut last: Local<Option<(Entity, Transform)>>,
 ) {
+    let pivot = match (settings.pivot, selection.as_slice().last()) {
+        (GizmoPivot::LastSelected, Some(&pivot)) => pivot,
+        _ => {
+            *last = None;
+            return;
+        }
+    };
+    // The gizmo moved the local transform this frame, the parents' global ones are still current.
+    let parent_global = |entity: Entity| {
+        parents
+            .get(entity)
+            .ok()
+            .and_then(|parent| globals.get(parent.get()).ok())
+            .copied()
+            .unwrap_or_default()
+    };
+    let Ok(local) = transforms.get(pivot).copied() else {
+        return;
+    };
+    let to = (parent_global(pivot) * local).compute_transform();
 
-    let selected_entities = &mut ui_state.selected_entities;
-    if let Some((action, entity)) = selected_entities.last_action() {
-        match action {
-            SelectionMode::Replace => {
-                for (e, _) in query.iter() {
-                    commands.entity(e).remove::<GizmoTarget>();
+    let dragging = targets.get(pivot).is_ok_and(GizmoTarget::is_active);
+    if let (true, Some((entity, from))) = (dragging, *last) {
+        if entity == pivot && from != to {
+            let rotation: Quat = to.rotation * from.rotation.inverse();
+            // An axis scaled from zero has no ratio, the followers keep their scale on it.
+            let scale: Vec3 = Vec3::select(
+                from.scale.abs().cmplt(Vec3::splat(f32::EPSILON)),
+                Vec3::ONE,
+                to.scale / from.scale,
+            );
+            let is_ancestor = |ancestor: Entity, mut entity: Entity| {
+                while let Ok(parent) = parents.get(entity) {
+                    entity = parent.get();
+                    if entity == ancestor {
+                        return true;
+                    }
+                }
+                false
+            };
+            let followers = roots(selection.as_slice(), |entity| parents.get(entity).ok().map(Parent::get));
+            for entity in followers {
+                if entity == pivot || is_ancestor(pivot, entity) || is_ancestor(entity, pivot) {
+                    continue;
                 }
-                commands.entity(entity).insert(GizmoTarget::default());
+                let parent = parent_global(entity);
+                let Ok(mut transform) = transforms.get_mut(entity) else {
+                    continue;
+                };
+                let mut global = (parent * *transform).compute_transform();
+                // Scaled along the pivot's own axes, like the pivot itself.
+                let offset = from.rotation.inverse() * (global.translation - from.translation);
+                global.translation = to.translation + to.rotation * (offset * scale);
+                global.rotation = rotation * global.rotation;
+                global.scale *= scale;
+                *transform = GlobalTransform::from(global).reparented_to(&parent);
             }
-            _ => {}
         }
     }
+    *last = Some((pivot, to));
+}
+
+#[derive(Default)]
+pub struct GizmoDrag {
+    dragging: bool,
+    start: Vec<(Entity, Transform)>,
+}
+
+/// Turns a whole gizmo drag into one [`TransformChange`].
+///
+/// The gizmo already moves the targets while dragging, so the start transforms are taken
+/// from the last frame before the drag became active and the command is only pushed, not executed.
+/// The whole selection is recorded, as entities following [`GizmoPivot::LastSelected`] move too.
+pub fn record_gizmo_drags(
+    mut history: ResMut<HistoryManager>,
+    selection: Res<EditorSelection>,
+    targets: Query<&GizmoTarget>,
+    transforms: Query<&Transform>,
+    mut drag: Local<GizmoDrag>,
+) {
+    let active = targets.iter().any(GizmoTarget::is_active);
+
+    if active {
+        drag.dragging = true;
+        return;
+    }
+
+    if drag.dragging {
+        drag.dragging = false;
+
+        let changed: Vec<TransformTarget> = drag
+            .start
+            .iter()
+            .filter_map(|(entity, from)| {
+                let to = transforms.get(*entity).ok()?;
+                (to != from).then(|| TransformTarget {
+                    entity: *entity,
+                    from: *from,
+                    to: *to,
+                })
+            })
+            .collect();
+
+        if !changed.is_empty() {
+            // Separate drags stay separate steps, however quickly they follow each other.
+            history.break_merge();
+            history.push(Box::new(TransformChange { targets: changed }));
+        }
+    }
+
+    drag.start = selection
+        .as_slice()
+        .iter()
+        .filter_map(|entity| Some((*entity, *transforms.get(*entity).ok()?)))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_only_the_edited_values() {
+        let steps = SnapSteps::default();
+        let before = Transform::from_xyz(0.1, 0.3, 0.0);
+        let mut transform = Transform::from_xyz(0.1, 0.62, 0.0).with_scale(Vec3::new(1.0, 1.0, 1.23));
+        snap_transform_edit(&steps, &before, &mut transform);
+
+        assert_eq!(transform.translation, Vec3::new(0.1, 0.5, 0.0));
+        assert!((transform.scale.z - 1.2).abs() < 1e-5);
+        assert_eq!(transform.scale.x, 1.0);
+        assert_eq!(transform.rotation, Quat::IDENTITY);
+    }
+
+    #[test]
+    fn snaps_edited_angles() {
+        let steps = SnapSteps::default();
+        let before = Transform::default();
+        let mut transform = Transform::from_rotation(Quat::from_rotation_y(20f32.to_radians()));
+        snap_transform_edit(&steps, &before, &mut transform);
+
+        let expected = Quat::from_rotation_y(15f32.to_radians());
+        assert!(transform.rotation.angle_between(expected) < 1e-4);
+    }
 }
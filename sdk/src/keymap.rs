@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::path::Path;
+use bevy::input::ButtonInput;
+use bevy::log::warn;
+use bevy::prelude::{KeyCode, Resource};
+use serde::{Deserialize, Serialize};
+
+/// Where [`EditorKeymap::load`] looks for user bindings, relative to the working directory.
+pub const KEYMAP_PATH: &str = "keymap.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EditorAction {
+    Undo,
+    Redo,
+    Delete,
+    Copy,
+    Paste,
+    Duplicate,
+    FlyForward,
+    FlyBackward,
+    FlyLeft,
+    FlyRight,
+    FlyFast,
+    FlySlow,
+    Focus,
+    GizmoTranslate,
+    GizmoRotate,
+    GizmoScale,
+    GizmoUniversal,
+    GizmoToggleSpace,
+    GizmoCyclePivot,
+    /// Held during a drag to invert the snapping toggle.
+    GizmoSnap,
+    SnapToGrid,
+}
+
+/// A key plus the modifiers that must be held with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub key: KeyCode,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl KeyChord {
+    pub const fn key(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    pub const fn ctrl(key: KeyCode) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::key(key)
+        }
+    }
+
+    pub const fn ctrl_shift(key: KeyCode) -> Self {
+        Self {
+            ctrl: true,
+            shift: true,
+            ..Self::key(key)
+        }
+    }
+
+    fn modifiers(input: &ButtonInput<KeyCode>) -> (bool, bool, bool) {
+        (
+            input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+            input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+        )
+    }
+
+    fn modifier_count(&self) -> u8 {
+        self.ctrl as u8 + self.shift as u8 + self.alt as u8
+    }
+
+    /// Triggered this frame with exactly the chord's modifiers, so Ctrl+Z doesn't fire on Ctrl+Shift+Z.
+    pub fn just_pressed(&self, input: &ButtonInput<KeyCode>) -> bool {
+        input.just_pressed(self.key) && Self::modifiers(input) == (self.ctrl, self.shift, self.alt)
+    }
+
+    /// Held with at least the chord's modifiers. Extra modifiers are allowed so
+    /// held actions combine, e.g. flying forward while holding the fast modifier.
+    pub fn pressed(&self, input: &ButtonInput<KeyCode>) -> bool {
+        let (ctrl, shift, alt) = Self::modifiers(input);
+        input.pressed(self.key) && (ctrl || !self.ctrl) && (shift || !self.shift) && (alt || !self.alt)
+    }
+}
+
+/// Either Control key on its own, the default for `GizmoSnap`. Flying slowly is on Alt instead,
+/// as holding Ctrl with a fly key would make chords like Ctrl+D, which duplicates.
+const CONTROL_LEFT: KeyChord = KeyChord::key(KeyCode::ControlLeft);
+const CONTROL_RIGHT: KeyChord = KeyChord::key(KeyCode::ControlRight);
+
+/// Maps editor actions to key chords. Systems should query this instead of checking `KeyCode`s directly.
+///
+/// User bindings are read from [`KEYMAP_PATH`]; actions missing there keep their defaults:
+///
+/// ```ron
+/// (
+///     bindings: {
+///         Undo: [(key: KeyZ, ctrl: true)],
+///         FlyForward: [(key: ArrowUp), (key: KeyW)],
+///     },
+/// )
+/// ```
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct EditorKeymap {
+    pub bindings: HashMap<EditorAction, Vec<KeyChord>>,
+}
+
+impl Default for EditorKeymap {
+    fn default() -> Self {
+        use EditorAction::*;
+
+        let bindings = [
+            (Undo, vec![KeyChord::ctrl(KeyCode::KeyZ)]),
+            (
+                Redo,
+                vec![KeyChord::ctrl(KeyCode::KeyY), KeyChord::ctrl_shift(KeyCode::KeyZ)],
+            ),
+            (Delete, vec![KeyChord::key(KeyCode::Delete)]),
+            (Copy, vec![KeyChord::ctrl(KeyCode::KeyC)]),
+            (Paste, vec![KeyChord::ctrl(KeyCode::KeyV)]),
+            (Duplicate, vec![KeyChord::ctrl(KeyCode::KeyD)]),
+            (FlyForward, vec![KeyChord::key(KeyCode::KeyW)]),
+            (FlyBackward, vec![KeyChord::key(KeyCode::KeyS)]),
+            (FlyLeft, vec![KeyChord::key(KeyCode::KeyA)]),
+            (FlyRight, vec![KeyChord::key(KeyCode::KeyD)]),
+            (FlyFast, vec![KeyChord::key(KeyCode::ShiftLeft)]),
+            (
+                FlySlow,
+                vec![KeyChord::key(KeyCode::AltLeft), KeyChord::key(KeyCode::AltRight)],
+            ),
+            (Focus, vec![KeyChord::key(KeyCode::KeyF)]),
+            (GizmoTranslate, vec![KeyChord::key(KeyCode::Digit1)]),
+            (GizmoRotate, vec![KeyChord::key(KeyCode::Digit2)]),
+            (GizmoScale, vec![KeyChord::key(KeyCode::Digit3)]),
+            (GizmoUniversal, vec![KeyChord::key(KeyCode::Digit4)]),
+            (GizmoToggleSpace, vec![KeyChord::key(KeyCode::KeyX)]),
+            (GizmoCyclePivot, vec![KeyChord::key(KeyCode::KeyP)]),
+            (GizmoSnap, vec![CONTROL_LEFT, CONTROL_RIGHT]),
+            (SnapToGrid, vec![KeyChord::ctrl(KeyCode::KeyG)]),
+        ]
+        .into_iter()
+        .collect();
+
+        Self { bindings }
+    }
+}
+
+impl EditorKeymap {
+    /// Loads the keymap from `path` on top of the defaults. A missing file is not an error.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let mut keymap = Self::default();
+        let path = path.as_ref();
+
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return keymap;
+        };
+        match ron::from_str::<EditorKeymap>(&contents) {
+            Ok(user) => keymap.bindings.extend(user.bindings),
+            Err(error) => warn!("Ignoring keymap {}: {error}", path.display()),
+        }
+        keymap
+    }
+
+    pub fn chords(&self, action: EditorAction) -> &[KeyChord] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn just_pressed(&self, action: EditorAction, input: &ButtonInput<KeyCode>) -> bool {
+        self.chords(action).iter().any(|chord| chord.just_pressed(input))
+    }
+
+    pub fn pressed(&self, action: EditorAction, input: &ButtonInput<KeyCode>) -> bool {
+        self.chords(action).iter().any(|chord| chord.pressed(input))
+    }
+
+    /// Like [`Self::pressed`], but not while the key is held as part of another action's chord
+    /// with more modifiers, so holding Ctrl+D to duplicate doesn't also fly right on D.
+    pub fn pressed_alone(&self, action: EditorAction, input: &ButtonInput<KeyCode>) -> bool {
+        self.chords(action).iter().any(|chord| {
+            chord.pressed(input)
+                && !self.bindings.iter().any(|(other, chords)| {
+                    *other != action
+                        && chords.iter().any(|other| {
+                            other.key == chord.key
+                                && other.modifier_count() > chord.modifier_count()
+                                && other.pressed(input)
+                        })
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(keys: &[KeyCode]) -> ButtonInput<KeyCode> {
+        let mut input = ButtonInput::default();
+        for key in keys {
+            input.press(*key);
+        }
+        input
+    }
+
+    #[test]
+    fn just_pressed_requires_exact_modifiers() {
+        let keymap = EditorKeymap::default();
+        let ctrl_z = input(&[KeyCode::ControlLeft, KeyCode::KeyZ]);
+        assert!(keymap.just_pressed(EditorAction::Undo, &ctrl_z));
+        assert!(!keymap.just_pressed(EditorAction::Redo, &ctrl_z));
+
+        let ctrl_shift_z = input(&[KeyCode::ControlLeft, KeyCode::ShiftLeft, KeyCode::KeyZ]);
+        assert!(!keymap.just_pressed(EditorAction::Undo, &ctrl_shift_z));
+        assert!(keymap.just_pressed(EditorAction::Redo, &ctrl_shift_z));
+
+        assert!(!keymap.just_pressed(EditorAction::Undo, &input(&[KeyCode::KeyZ])));
+    }
+
+    #[test]
+    fn either_side_of_a_modifier_counts() {
+        let chord = KeyChord::ctrl(KeyCode::KeyC);
+        assert!(chord.just_pressed(&input(&[KeyCode::ControlRight, KeyCode::KeyC])));
+        assert!(!chord.just_pressed(&input(&[KeyCode::AltRight, KeyCode::KeyC])));
+    }
+
+    #[test]
+    fn just_pressed_ignores_held_keys() {
+        let mut held = input(&[KeyCode::ControlLeft, KeyCode::KeyZ]);
+        held.clear();
+        assert!(!KeyChord::ctrl(KeyCode::KeyZ).just_pressed(&held));
+        assert!(KeyChord::ctrl(KeyCode::KeyZ).pressed(&held));
+    }
+
+    #[test]
+    fn pressed_alone_yields_to_chords_with_more_modifiers() {
+        let keymap = EditorKeymap::default();
+        assert!(keymap.pressed_alone(EditorAction::FlyRight, &input(&[KeyCode::KeyD])));
+        assert!(!keymap.pressed_alone(
+            EditorAction::FlyRight,
+            &input(&[KeyCode::ControlLeft, KeyCode::KeyD])
+        ));
+        // Nothing else is bound to Ctrl+W, so it still flies.
+        assert!(keymap.pressed_alone(
+            EditorAction::FlyForward,
+            &input(&[KeyCode::ControlLeft, KeyCode::KeyW])
+        ));
+    }
+
+    #[test]
+    fn either_control_key_snaps_and_either_alt_key_slows() {
+        let keymap = EditorKeymap::default();
+        for control in [KeyCode::ControlLeft, KeyCode::ControlRight] {
+            assert!(keymap.pressed(EditorAction::GizmoSnap, &input(&[control])));
+        }
+        for alt in [KeyCode::AltLeft, KeyCode::AltRight] {
+            assert!(keymap.pressed(EditorAction::FlySlow, &input(&[alt])));
+        }
+    }
+
+    #[test]
+    fn ctrl_d_duplicates_while_alt_d_flies_slowly() {
+        let keymap = EditorKeymap::default();
+        let ctrl_d = input(&[KeyCode::ControlLeft, KeyCode::KeyD]);
+        assert!(keymap.just_pressed(EditorAction::Duplicate, &ctrl_d));
+        assert!(!keymap.pressed_alone(EditorAction::FlyRight, &ctrl_d));
+        assert!(!keymap.pressed(EditorAction::FlySlow, &ctrl_d));
+
+        let alt_d = input(&[KeyCode::AltLeft, KeyCode::KeyD]);
+        assert!(!keymap.just_pressed(EditorAction::Duplicate, &alt_d));
+        assert!(keymap.pressed_alone(EditorAction::FlyRight, &alt_d));
+        assert!(keymap.pressed(EditorAction::FlySlow, &alt_d));
+    }
+
+    #[test]
+    fn pressed_allows_extra_modifiers() {
+        let chord = KeyChord::key(KeyCode::KeyW);
+        assert!(chord.pressed(&input(&[KeyCode::ShiftLeft, KeyCode::KeyW])));
+        assert!(!KeyChord::ctrl(KeyCode::KeyW).pressed(&input(&[KeyCode::KeyW])));
+    }
+
+    #[test]
+    fn user_bindings_replace_only_their_actions() {
+        let path = std::env::temp_dir().join(format!("keymap-test-{}.ron", std::process::id()));
+        std::fs::write(&path, "(bindings: { Undo: [(key: KeyU)] })").unwrap();
+        let keymap = EditorKeymap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(keymap.chords(EditorAction::Undo), &[KeyChord::key(KeyCode::KeyU)]);
+        assert_eq!(
+            keymap.chords(EditorAction::Copy),
+            EditorKeymap::default().chords(EditorAction::Copy)
+        );
+    }
+}
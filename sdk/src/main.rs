@@ -1,16 +1,19 @@
 use bevy::prelude::*;
 use bevy_asset::{ReflectAsset, UntypedAssetId};
 use bevy_egui::{EguiContext, EguiContextSettings, EguiPostUpdateSet};
-use bevy_inspector_egui::bevy_inspector::hierarchy::{hierarchy_ui, SelectedEntities};
+use bevy_inspector_egui::bevy_inspector::hierarchy::Hierarchy;
 use bevy_inspector_egui::bevy_inspector::{
     self, ui_for_entities_shared_components, ui_for_entity_with_children,
 };
 use bevy_inspector_egui::DefaultInspectorConfigPlugin;
-use camera::{camera_movement, SdkCamera};
+use camera::{camera_movement, focus_selection, SdkCamera};
+use domain::{DomainPlugin, GameplaySet, PrefabInstance};
 use std::any::TypeId;
+use std::path::Path;
+use std::panic::{self, AssertUnwindSafe};
 use bevy::ecs::observer::TriggerTargets;
 use bevy::picking::backend::PointerHits;
-use bevy::picking::focus::HoverMap;
+use bevy::picking::pointer::PointerId;
 use bevy::picking::pointer::{PointerAction, PointerInput, PointerMap};
 use bevy::picking::pointer::PointerAction::Pressed;
 // use bevy_mod_picking::backends::egui::EguiPointer;
@@ -19,30 +22,80 @@ use bevy_reflect::TypeRegistry;
 use bevy_render::camera::{CameraProjection, Viewport};
 use bevy_window::{PresentMode, PrimaryWindow, Window, WindowMode, WindowTheme};
 use egui_dock::{DockArea, DockState, NodeIndex, Style};
+use serde::{Deserialize, Serialize};
 use transform_gizmo_bevy::{GizmoCamera, GizmoTarget, TransformGizmoPlugin};
-#[cfg(egui_dock_gizmo)]
-use transform_gizmo_egui::GizmoMode;
-use crate::editor_commands::{handle_input, HistoryManager};
-use crate::gizmo::draw_gizmo;
-
-/// Placeholder type if gizmo is disabled.
-#[cfg(not(egui_dock_gizmo))]
-#[derive(Clone, Copy)]
-struct GizmoMode;
+use crate::autosave::{
+    autosave, discard_recovery, find_recovery, recovery_ui, restore_recovery, write_recovery,
+    Autosave, Recovery, RecoveryChoice,
+};
+use crate::editor_commands::{
+    handle_input, send_history_events, with_descendants, CommandExecuted, CommandRedone,
+    CommandUndone, HistoryManager, PatchTarget, ReflectSnapshot,
+};
+use crate::gizmo::{
+    apply_gizmo_settings, follow_last_selected, gizmo_shortcuts, gizmo_toolbar_ui,
+    record_gizmo_drags, snap_transform_edit, GizmoSettings,
+};
+use crate::entity_commands::{apply_hierarchy_edits, hierarchy_context_menu, selection_roots};
+use crate::editor_only::EditorOnly;
+use crate::keymap::{EditorKeymap, KEYMAP_PATH};
+use crate::marquee::{Marquee, MIN_DRAG_DISTANCE};
+use crate::play_mode::{
+    finish_step, gameplay_running, in_edit_mode, play_controls_ui, restore_scene, snapshot_scene,
+    EditorMode, PlayStep,
+};
+use crate::prefab::{
+    instantiate_prefab, prefab_inspector_ui, save_prefab, update_prefab_instances, PrefabLibrary,
+    DEFAULT_PREFAB_PATH,
+};
+use crate::scene_file::{
+    new_scene, open_scene, save_scene, scene_path_ui, CurrentScene, ScenePathPrompt,
+    DEFAULT_SCENE_PATH,
+};
+use crate::scene_guid::{assign_scene_guid, entity_links_ui};
+use crate::selection::{
+    send_selection_changed, sync_gizmo_targets, EditorSelection, SelectionChanged,
+};
+use crate::scene_template::{
+    load_startup_scene, new_scene_ui, register_user_templates, scene_templates, SceneTemplate,
+    DEFAULT_TEMPLATE,
+};
+use crate::unsaved_changes::{
+    handle_close_requested, run_pending_action, unsaved_changes_ui, update_window_title,
+    PendingAction, WINDOW_TITLE,
+};
 
 
+mod autosave;
 mod camera;
+mod clipboard;
+mod command_record;
 mod gizmo;
-mod domain;
 mod editor_commands;
+mod editor_only;
+mod entity_commands;
+mod keymap;
+mod marquee;
+mod play_mode;
+mod prefab;
+mod scene_file;
+mod scene_guid;
+mod scene_template;
+mod selection;
+mod unsaved_changes;
 
 fn main() {
-    App::new()
+    let mut app = App::new();
+    // Before the `AssetPlugin` in `DefaultPlugins` builds the asset sources.
+    register_user_templates(&mut app);
+    app
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
-                title: "RRay SDK".into(),
+                title: WINDOW_TITLE.into(),
                 ..default()
             }),
+            // Closing is confirmed by `handle_close_requested` when there are unsaved changes.
+            close_when_requested: false,
             ..default()
         }))
         // .add_plugins(bevy_framepace::FramepacePlugin) // reduces input lag
@@ -50,10 +103,39 @@ fn main() {
         .add_plugins(MeshPickingPlugin)
         .add_plugins(bevy_egui::EguiPlugin)
         .add_plugins(TransformGizmoPlugin)
+        .add_plugins(DomainPlugin)
         // .add_plugins(bevy_mod_picking::plugins::DefaultPickingPlugins)
         .insert_resource(UiState::new())
         .insert_resource(HistoryManager::new())
-        .add_systems(Startup, (init_window, setup).chain())
+        .insert_resource(EditorKeymap::load(KEYMAP_PATH))
+        .init_resource::<CurrentScene>()
+        .init_resource::<PrefabLibrary>()
+        .init_resource::<Autosave>()
+        .init_state::<EditorMode>()
+        .init_resource::<PlayStep>()
+        .configure_sets(Update, GameplaySet.run_if(gameplay_running))
+        .add_systems(
+            Update,
+            finish_step
+                .after(GameplaySet)
+                .run_if(in_state(EditorMode::Paused)),
+        )
+        .add_systems(
+            OnTransition {
+                exited: EditorMode::Edit,
+                entered: EditorMode::Play,
+            },
+            snapshot_scene,
+        )
+        .add_systems(OnEnter(EditorMode::Edit), restore_scene)
+        .add_event::<CommandExecuted>()
+        .add_event::<CommandUndone>()
+        .add_event::<CommandRedone>()
+        .init_resource::<EditorSelection>()
+        .init_resource::<GizmoSettings>()
+        .add_event::<SelectionChanged>()
+        .add_observer(assign_scene_guid)
+        .add_systems(Startup, (init_window, spawn_editor_camera, load_startup_scene).chain())
         .add_systems(
             PostUpdate,
             show_ui_system
@@ -62,41 +144,138 @@ fn main() {
                 .before(TransformSystem::TransformPropagate),
         )
         .add_systems(PostUpdate, set_camera_viewport.after(show_ui_system))
+        // After the UI, so commands recorded by the inspector in `show_ui_system` go out the same frame.
+        .add_systems(PostUpdate, send_history_events.after(show_ui_system))
+        .add_systems(PostUpdate, send_selection_changed.after(show_ui_system))
         .add_systems(Update, (
-            draw_gizmo, 
+            sync_gizmo_targets.after(pick_system),
+            gizmo_shortcuts,
+            apply_gizmo_settings,
+            follow_last_selected.before(record_gizmo_drags),
+            record_gizmo_drags,
             camera_movement, 
+            focus_selection,
             handle_input,
-            pick_system
-        ))
+            pick_system,
+            update_prefab_instances,
+            autosave,
+        ).run_if(in_state(EditorMode::Edit)))
+        .add_systems(Update, (update_window_title, handle_close_requested))
         .register_type::<SdkCamera>()
+        .register_type::<EditorOnly>()
+        .register_required_components::<GizmoCamera, EditorOnly>()
         .register_type::<Option<Handle<Image>>>()
         .register_type::<AlphaMode>()
         .run();
 }
 
+/// Clicks closer than this to the last one count as the same spot and cycle through its hits.
+const PICK_CYCLE_DISTANCE: f32 = MIN_DRAG_DISTANCE;
+
+/// What the last click in the viewport hit, front to back.
+#[derive(Default)]
+pub struct PickCycle {
+    position: Vec2,
+    hits: Vec<Entity>,
+    index: usize,
+    /// Where the left button went down over the viewport, in egui points, until it's released.
+    press: Option<Vec2>,
+}
+
+impl PickCycle {
+    /// The entity a click at `position` picks out of `hits`: the next one after the last pick
+    /// when the click lands on the same spot with the same hits, otherwise the front one.
+    fn pick(&mut self, position: Vec2, hits: Vec<Entity>) -> Entity {
+        let same_spot = position.distance(self.position) < PICK_CYCLE_DISTANCE && hits == self.hits;
+        self.index = if same_spot { (self.index + 1) % hits.len() } else { 0 };
+        self.position = position;
+        self.hits = hits;
+        self.hits[self.index]
+    }
+}
+
+/// Selects what's under the cursor when a left click is released. Presses that move further
+/// than [`MIN_DRAG_DISTANCE`] are marquee drags instead. Repeated clicks on the same spot step
+/// through everything there, front to back; Alt picks the parent of what was hit.
+#[allow(clippy::too_many_arguments)]
 pub fn pick_system(
-    mut mouse_events: Res<ButtonInput<MouseButton>>,
-    targeted: Query<(Entity, Option<&mut GizmoTarget>)>,
-    hover_map: Res<HoverMap>,
-    mut commands: Commands
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut pointer_hits: EventReader<PointerHits>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    egui_settings: Query<&EguiContextSettings, With<PrimaryWindow>>,
+    editor_only: Query<(), With<EditorOnly>>,
+    parents: Query<&Parent>,
+    gizmo_targets: Query<&GizmoTarget>,
+    mut selection: ResMut<EditorSelection>,
+    mut ui_state: ResMut<UiState>,
+    mut cycle: Local<PickCycle>,
 ) {
-    if mouse_events.just_pressed(MouseButton::Left) {
-        for (_pointer, pointer_map) in hover_map.iter() {
-            println!("{:?}", pointer_map);
-            let option = pointer_map.iter().next();
-            if let Some((entity, target)) = option {
-                println!("{:?} -> {:?}", _pointer, entity);
-                for (e, _) in targeted.iter().filter(|(e, _)| entity != e) {
-                    commands.entity(e).remove::<GizmoTarget>();
-                }
+    let mut picks: Vec<(Entity, f32)> = pointer_hits
+        .read()
+        .filter(|hits| hits.pointer == PointerId::Mouse)
+        .flat_map(|hits| hits.picks.iter().map(|(entity, hit)| (*entity, hit.depth)))
+        .collect();
 
-                commands.entity(entity.clone()).insert(GizmoTarget::default());
-            }
+    let (Ok(window), Ok(egui_settings)) = (windows.get_single(), egui_settings.get_single()) else {
+        return;
+    };
+    let Some(position) = window.cursor_position() else {
+        return;
+    };
+    // The viewport rect is in egui points, the cursor in logical pixels.
+    let point = position / egui_settings.scale_factor;
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        // A press that grabs a gizmo handle doesn't change the selection.
+        let over_viewport = ui_state.viewport_hovered
+            && ui_state.viewport_rect.contains(egui::pos2(point.x, point.y));
+        let on_gizmo = gizmo_targets.iter().any(GizmoTarget::is_focused);
+        cycle.press = (over_viewport && !on_gizmo).then_some(point);
+        return;
+    }
+    if !mouse_buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(press) = cycle.press.take() else {
+        return;
+    };
+    if press.distance(point) >= MIN_DRAG_DISTANCE {
+        return;
+    }
+
+    picks.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    let alt = keyboard_input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    let mut hits: Vec<Entity> = Vec::new();
+    for (entity, _) in picks {
+        if editor_only.contains(entity) {
+            continue;
+        }
+        let entity = match parents.get(entity) {
+            Ok(parent) if alt && !editor_only.contains(parent.get()) => parent.get(),
+            _ => entity,
+        };
+        if !hits.contains(&entity) {
+            hits.push(entity);
         }
     }
+    if hits.is_empty() {
+        return;
+    }
+
+    let entity = cycle.pick(point, hits);
+    if keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        selection.toggle(entity);
+    } else if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        selection.add(entity);
+    } else {
+        selection.replace(entity);
+    }
+    ui_state.selection = InspectorSelection::Entities;
 }
 
 #[derive(Component)]
+#[require(EditorOnly)]
 struct MainCamera;
 
 fn show_ui_system(world: &mut World) {
@@ -109,7 +288,16 @@ fn show_ui_system(world: &mut World) {
     let mut egui_context = egui_context.clone();
 
     world.resource_scope::<UiState, _>(|world, mut ui_state| {
-        ui_state.ui(world, egui_context.get_mut())
+        // A panicking widget (e.g. in the inspector) takes the editor down, but not the unsaved work.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            ui_state.ui(world, egui_context.get_mut())
+        }));
+        if let Err(payload) = result {
+            if in_edit_mode(world) {
+                write_recovery(world, &ui_state.state);
+            }
+            panic::resume_unwind(payload);
+        }
     });
 }
 
@@ -168,9 +356,19 @@ enum InspectorSelection {
 struct UiState {
     state: DockState<EguiWindow>,
     viewport_rect: egui::Rect,
-    selected_entities: SelectedEntities,
+    /// Whether the pointer is over the viewport itself, rather than over a panel, window or
+    /// widget on top of it that egui wants the pointer for.
+    viewport_hovered: bool,
     selection: InspectorSelection,
-    gizmo_mode: GizmoMode,
+    /// What the Inspector tab showed last frame, to record its edits.
+    inspector_snapshot: ReflectSnapshot,
+    marquee: Marquee,
+    pending_action: Option<PendingAction>,
+    scene_path_prompt: Option<(ScenePathPrompt, String)>,
+    /// Templates offered by File → New while its picker is open.
+    template_picker: Option<Vec<SceneTemplate>>,
+    /// Unsaved work from the last session, offered once at startup.
+    recovery: Option<Recovery>,
 }
 
 impl UiState {
@@ -180,50 +378,172 @@ impl UiState {
         let [game, _inspector] =
             tree.split_right(NodeIndex::root(), 0.75, vec![EguiWindow::Inspector]);
         let [game, _hierarchy] = tree.split_left(game, 0.2, vec![EguiWindow::Hierarchy]);
-        let [_game, _bottom] =
-            tree.split_below(game, 0.8, vec![EguiWindow::Resources, EguiWindow::Assets]);
+        let [_game, _bottom] = tree.split_below(
+            game,
+            0.8,
+            vec![EguiWindow::Resources, EguiWindow::Assets, EguiWindow::History],
+        );
 
         Self {
             state,
-            selected_entities: SelectedEntities::default(),
             selection: InspectorSelection::Entities,
             viewport_rect: egui::Rect::NOTHING,
-            #[cfg(egui_dock_gizmo)]
-            gizmo_mode: GizmoMode::Translate,
-            #[cfg(not(egui_dock_gizmo))]
-            gizmo_mode: GizmoMode,
+            inspector_snapshot: ReflectSnapshot::default(),
+            viewport_hovered: false,
+            marquee: Marquee::default(),
+            pending_action: None,
+            scene_path_prompt: None,
+            template_picker: None,
+            recovery: find_recovery(),
         }
     }
 
     fn ui(&mut self, world: &mut World, ctx: &mut egui::Context) {
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                let editing = in_edit_mode(world);
+                ui.add_enabled_ui(editing, |ui| {
+                    ui.menu_button("File", |ui| self.file_menu(world, ui));
+                });
+                ui.separator();
+                play_controls_ui(ui, world);
+            });
+        });
+
+        // Set again by the GameView tab, if it's shown.
+        self.viewport_hovered = false;
         let mut tab_viewer = TabViewer {
             world,
             viewport_rect: &mut self.viewport_rect,
-            selected_entities: &mut self.selected_entities,
+            viewport_hovered: &mut self.viewport_hovered,
             selection: &mut self.selection,
-            gizmo_mode: self.gizmo_mode,
+            inspector_snapshot: &mut self.inspector_snapshot,
+            marquee: &mut self.marquee,
         };
         DockArea::new(&mut self.state)
             .style(Style::from_egui(ctx.style().as_ref()))
             .show(ctx, &mut tab_viewer);
+
+        if let Some(template) = new_scene_ui(ctx, &mut self.template_picker) {
+            if world.resource::<HistoryManager>().is_dirty() {
+                self.pending_action = Some(PendingAction::New(template));
+            } else {
+                new_scene(world, &template);
+            }
+        }
+
+        match scene_path_ui(ctx, &mut self.scene_path_prompt) {
+            Some((ScenePathPrompt::Open, path)) => {
+                if world.resource::<HistoryManager>().is_dirty() {
+                    self.pending_action = Some(PendingAction::Open(path));
+                } else {
+                    open_scene(world, &path);
+                }
+            }
+            Some((ScenePathPrompt::SaveAs, path)) => {
+                if let Err(error) = save_scene(world, &path) {
+                    warn!("Failed to save scene to {}: {error}", path.display());
+                }
+            }
+            Some((ScenePathPrompt::SavePrefab(root), path)) => {
+                if let Err(error) = save_prefab(world, root, &path) {
+                    warn!("Failed to save prefab to {}: {error}", path.display());
+                }
+            }
+            Some((ScenePathPrompt::InstantiatePrefab, path)) => instantiate_prefab(world, &path),
+            None => {}
+        }
+
+        if let Some(action) = unsaved_changes_ui(ctx, &mut self.pending_action) {
+            run_pending_action(world, action);
+        }
+
+        match recovery_ui(ctx, &mut self.recovery) {
+            Some(RecoveryChoice::Restore(recovery)) => {
+                restore_recovery(world, &mut self.state, recovery);
+            }
+            Some(RecoveryChoice::Discard) => {
+                discard_recovery(&mut world.resource_mut::<Autosave>());
+                new_scene(world, Path::new(DEFAULT_TEMPLATE));
+            }
+            None => {}
+        }
+    }
+
+    fn file_menu(&mut self, world: &mut World, ui: &mut egui::Ui) {
+        let current = world.resource::<CurrentScene>().path.clone();
+        let suggested = current
+            .as_ref()
+            .map_or(DEFAULT_SCENE_PATH.to_string(), |path| path.display().to_string());
+
+        if ui.button("New…").clicked() {
+            self.template_picker = Some(scene_templates());
+            ui.close_menu();
+        }
+        if ui.button("Open…").clicked() {
+            self.scene_path_prompt = Some((ScenePathPrompt::Open, suggested.clone()));
+            ui.close_menu();
+        }
+        if ui.button("Save").clicked() {
+            match &current {
+                Some(path) => {
+                    if let Err(error) = save_scene(world, path) {
+                        warn!("Failed to save scene to {}: {error}", path.display());
+                    }
+                }
+                None => self.scene_path_prompt = Some((ScenePathPrompt::SaveAs, suggested.clone())),
+            }
+            ui.close_menu();
+        }
+        if ui.button("Save As…").clicked() {
+            self.scene_path_prompt = Some((ScenePathPrompt::SaveAs, suggested));
+            ui.close_menu();
+        }
+
+        ui.separator();
+        let selected = match world.resource::<EditorSelection>().as_slice() {
+            &[entity] => Some(entity),
+            _ => None,
+        };
+        if ui
+            .add_enabled(selected.is_some(), egui::Button::new("Save Selection as Prefab…"))
+            .clicked()
+        {
+            if let Some(root) = selected {
+                self.scene_path_prompt = Some((
+                    ScenePathPrompt::SavePrefab(root),
+                    DEFAULT_PREFAB_PATH.to_string(),
+                ));
+            }
+            ui.close_menu();
+        }
+        if ui.button("Instantiate Prefab…").clicked() {
+            self.scene_path_prompt = Some((
+                ScenePathPrompt::InstantiatePrefab,
+                DEFAULT_PREFAB_PATH.to_string(),
+            ));
+            ui.close_menu();
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 enum EguiWindow {
     GameView,
     Hierarchy,
     Resources,
     Assets,
     Inspector,
+    History,
 }
 
 struct TabViewer<'a> {
     world: &'a mut World,
-    selected_entities: &'a mut SelectedEntities,
     selection: &'a mut InspectorSelection,
     viewport_rect: &'a mut egui::Rect,
-    gizmo_mode: GizmoMode,
+    inspector_snapshot: &'a mut ReflectSnapshot,
+    viewport_hovered: &'a mut bool,
+    marquee: &'a mut Marquee,
 }
 
 impl egui_dock::TabViewer for TabViewer<'_> {
@@ -237,42 +557,153 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             EguiWindow::GameView => {
                 *self.viewport_rect = ui.clip_rect();
 
-                // draw_gizmo(ui, self.world, self.selected_entities, self.gizmo_mode);
+                if in_edit_mode(self.world) {
+                    let response = self.marquee.ui(ui, self.world, *self.viewport_rect);
+                    *self.viewport_hovered = response.hovered();
+                    if response.changed() {
+                        *self.selection = InspectorSelection::Entities;
+                    }
+                    gizmo_toolbar_ui(ui, self.world);
+                }
             }
             EguiWindow::Hierarchy => {
-                let selected = hierarchy_ui(self.world, ui, self.selected_entities);
+                // Hierarchy edits are recorded in the history, so they are only offered while editing.
+                let editing = in_edit_mode(self.world);
+                let roots = selection_roots(self.world);
+                let mut edits = Vec::new();
+                let mut context_menu =
+                    |ui: &mut egui::Ui, entity, world: &mut World, edits: &mut Vec<_>| {
+                        hierarchy_context_menu(ui, entity, world, &roots, edits)
+                    };
+                let selected = self
+                    .world
+                    .resource_scope::<EditorSelection, _>(|world, mut selection| {
+                        Hierarchy {
+                            world,
+                            type_registry: &type_registry,
+                            selected: selection.selected_entities_mut(),
+                            context_menu: if editing { Some(&mut context_menu) } else { None },
+                            shortcircuit_entity: None,
+                            extra_state: &mut edits,
+                        }
+                        .show::<Without<EditorOnly>>(ui)
+                    });
+                apply_hierarchy_edits(self.world, edits);
                 if selected {
                     *self.selection = InspectorSelection::Entities;
                 }
             }
             EguiWindow::Resources => select_resource(ui, &type_registry, self.selection),
             EguiWindow::Assets => select_asset(ui, &type_registry, self.world, self.selection),
-            EguiWindow::Inspector => match *self.selection {
-                InspectorSelection::Entities => match self.selected_entities.as_slice() {
-                    &[entity] => ui_for_entity_with_children(self.world, entity, ui),
-                    entities => ui_for_entities_shared_components(self.world, entities, ui),
-                },
-                InspectorSelection::Resource(type_id, ref name) => {
-                    ui.label(name);
-                    bevy_inspector::by_type_id::ui_for_resource(
-                        self.world,
-                        type_id,
-                        ui,
-                        name,
-                        &type_registry,
-                    )
+            EguiWindow::History => {
+                // Steps recorded before Play would apply to the simulated world.
+                let editing = in_edit_mode(self.world);
+                ui.add_enabled_ui(editing, |ui| history_ui(ui, self.world));
+            }
+            EguiWindow::Inspector => {
+                // Edits made while playing are thrown away on Stop, so they aren't undo steps.
+                let editing = in_edit_mode(self.world);
+                let selected = self.world.resource::<EditorSelection>().as_slice().to_vec();
+
+                // Before the snapshot, so changes made by these panels aren't recorded twice.
+                if let (true, InspectorSelection::Entities, &[entity]) =
+                    (editing, &*self.selection, selected.as_slice())
+                {
+                    entity_links_ui(ui, self.world, entity);
                 }
-                InspectorSelection::Asset(type_id, ref name, handle) => {
-                    ui.label(name);
-                    bevy_inspector::by_type_id::ui_for_asset(
-                        self.world,
-                        type_id,
-                        handle,
-                        ui,
-                        &type_registry,
-                    );
+                let prefab_instance = match (editing, &*self.selection, selected.as_slice()) {
+                    (true, InspectorSelection::Entities, &[entity]) => {
+                        self.world.get::<PrefabInstance>(entity).is_some().then_some(entity)
+                    }
+                    _ => None,
+                };
+                let mut prefab_edit = None;
+
+                // Inspector widgets mutate the world directly, so diff reflected values around them
+                // to turn every edit into an undoable ReflectPatchCommand.
+                let targets = match *self.selection {
+                    InspectorSelection::Entities => match selected.as_slice() {
+                        &[entity] => ReflectSnapshot::entity_targets(
+                            self.world,
+                            with_descendants(self.world, entity),
+                            &type_registry,
+                        ),
+                        entities => ReflectSnapshot::entity_targets(
+                            self.world,
+                            entities.iter().copied(),
+                            &type_registry,
+                        ),
+                    },
+                    InspectorSelection::Resource(type_id, _) => vec![PatchTarget::Resource(type_id)],
+                    InspectorSelection::Asset(..) => Vec::new(),
+                };
+                self.inspector_snapshot.update(self.world, targets, &type_registry);
+
+                match *self.selection {
+                    InspectorSelection::Entities => match selected.as_slice() {
+                        &[entity] if prefab_instance.is_some() => {
+                            prefab_edit = prefab_inspector_ui(ui, self.world, entity);
+                        }
+                        &[entity] => ui_for_entity_with_children(self.world, entity, ui),
+                        entities => ui_for_entities_shared_components(self.world, entities, ui),
+                    },
+                    InspectorSelection::Resource(type_id, ref name) => {
+                        ui.label(name);
+                        bevy_inspector::by_type_id::ui_for_resource(
+                            self.world,
+                            type_id,
+                            ui,
+                            name,
+                            &type_registry,
+                        )
+                    }
+                    InspectorSelection::Asset(type_id, ref name, handle) => {
+                        ui.label(name);
+                        bevy_inspector::by_type_id::ui_for_asset(
+                            self.world,
+                            type_id,
+                            handle,
+                            ui,
+                            &type_registry,
+                        );
+                    }
                 }
-            },
+
+                // Numeric edits snap like gizmo drags while snapping is on.
+                let settings = *self.world.resource::<GizmoSettings>();
+                if let (true, true, InspectorSelection::Entities) =
+                    (editing, settings.snapping, &*self.selection)
+                {
+                    for &entity in &selected {
+                        let target = PatchTarget::Component(entity, TypeId::of::<Transform>());
+                        let Some(before) = self
+                            .inspector_snapshot
+                            .before(target)
+                            .and_then(|before| before.try_downcast_ref::<Transform>())
+                            .copied()
+                        else {
+                            continue;
+                        };
+                        let Some(mut transform) = self.world.get_mut::<Transform>(entity) else {
+                            continue;
+                        };
+                        let mut snapped = *transform;
+                        snap_transform_edit(&settings.snap, &before, &mut snapped);
+                        if snapped != *transform {
+                            *transform = snapped;
+                        }
+                    }
+                }
+
+                let command = self.inspector_snapshot.diff(self.world, &type_registry);
+                if let Some(command) = command.filter(|_| editing) {
+                    self.world.resource_mut::<HistoryManager>().push(Box::new(command));
+                }
+                // After the diff, the edit is its own undo step.
+                if let (Some(edit), Some(entity)) = (prefab_edit, prefab_instance) {
+                    edit.apply(self.world, entity);
+                }
+            }
         }
     }
 
@@ -355,128 +786,55 @@ fn select_asset(
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let box_size = 2.0;
-    let box_thickness = 0.15;
-    let box_offset = (box_size + box_thickness) / 2.0;
+enum HistoryJump {
+    Undo(usize),
+    Redo(usize),
+}
 
-    // left - red
-    let mut transform = Transform::from_xyz(-box_offset, box_offset, 0.0);
-    transform.rotate(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+fn history_ui(ui: &mut egui::Ui, world: &mut World) {
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        ui.checkbox(&mut history.save_with_scene, "Save with scene");
+        let undo_len = history.undo_stack().len();
+        let mut jump = None;
 
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(box_size, box_thickness, box_size))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(0.63, 0.065, 0.05),
-            ..Default::default()
-        })),
-        transform,
-    ));
-    // right - green
-    let mut transform = Transform::from_xyz(box_offset, box_offset, 0.0);
-    transform.rotate(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(box_size, box_thickness, box_size))),
-        transform,
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(0.14, 0.45, 0.091),
-            ..Default::default()
-        })),
-    ));
-    // bottom - white
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size,
-        ))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        })),
-    ));
-    // top - white
-    let transform = Transform::from_xyz(0.0, 2.0 * box_offset, 0.0);
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size,
-        ))),
-        transform,
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        })),
-    ));
-    // back - white
-    let mut transform = Transform::from_xyz(0.0, box_offset, -box_offset);
-    transform.rotate(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2));
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size + 2.0 * box_thickness,
-        ))),
-        transform,
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        })),
-    ));
-
-    // ambient light
-    commands.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 0.02,
-    });
-    // top light
-    commands
-        .spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(0.4, 0.4))),
-            Transform::from_matrix(Mat4::from_scale_rotation_translation(
-                Vec3::ONE,
-                Quat::from_rotation_x(std::f32::consts::PI),
-                Vec3::new(0.0, box_size + 0.5 * box_thickness, 0.0),
-            )),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: Color::WHITE,
-                emissive: LinearRgba::WHITE * 100.0,
-                ..Default::default()
-            })),
-        ))
-        .with_children(|builder| {
-            builder.spawn((
-                PointLight {
-                    color: Color::WHITE,
-                    intensity: 25000.0,
-                    ..Default::default()
-                },
-                Transform::from_translation((box_thickness + 0.05) * Vec3::Y),
-            ));
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            if ui.selectable_label(undo_len == 0, "Initial state").clicked() {
+                jump = Some(HistoryJump::Undo(undo_len));
+            }
+            for (index, command) in history.undo_stack().enumerate() {
+                if ui
+                    .selectable_label(index + 1 == undo_len, command.label())
+                    .clicked()
+                {
+                    jump = Some(HistoryJump::Undo(undo_len - index - 1));
+                }
+            }
+            for (index, command) in history.redo_stack().enumerate() {
+                let label = egui::RichText::new(command.label()).weak();
+                if ui.selectable_label(false, label).clicked() {
+                    jump = Some(HistoryJump::Redo(index + 1));
+                }
+            }
         });
 
+        let result = match jump {
+            Some(HistoryJump::Undo(steps)) => (0..steps).try_for_each(|_| history.undo(world)),
+            Some(HistoryJump::Redo(steps)) => (0..steps).try_for_each(|_| history.redo(world)),
+            None => Ok(()),
+        };
+        if let Err(error) = result {
+            warn!("Dropped editor command from history: {error}");
+        }
+    });
+}
 
-
-    // directional light
-    commands.spawn((
-        DirectionalLight {
-            illuminance: 2000.0,
-            ..default()
-        },
-        Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::PI / 2.0)),
-    ));
-
-    // camera
+/// The editor's own camera. It isn't part of the scene, so it stays put across New and Open.
+fn spawn_editor_camera(mut commands: Commands) {
     commands.spawn((
         Name::new("Camera"),
         Camera3d::default(),
-        Transform::from_xyz(0.0, box_offset, 4.0)
-            .looking_at(Vec3::new(0.0, box_offset, 0.0), Vec3::Y),
+        // Until a template moves it, see `LevelInfo::camera_start`.
+        Transform::from_xyz(0.0, 2.0, 8.0).looking_at(Vec3::ZERO, Vec3::Y),
         MainCamera,
         SdkCamera::default(),
         GizmoCamera,
@@ -485,3 +843,32 @@ fn setup(
     ));
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_cycle_steps_through_hits_on_the_same_spot() {
+        let hits = vec![Entity::from_raw(1), Entity::from_raw(2), Entity::from_raw(3)];
+        let mut cycle = PickCycle::default();
+        let spot = Vec2::new(100.0, 100.0);
+
+        assert_eq!(cycle.pick(spot, hits.clone()), hits[0]);
+        assert_eq!(cycle.pick(spot + Vec2::X, hits.clone()), hits[1]);
+        assert_eq!(cycle.pick(spot, hits.clone()), hits[2]);
+        assert_eq!(cycle.pick(spot, hits.clone()), hits[0]);
+    }
+
+    #[test]
+    fn pick_cycle_restarts_elsewhere_or_on_other_hits() {
+        let hits = vec![Entity::from_raw(1), Entity::from_raw(2)];
+        let mut cycle = PickCycle::default();
+        let spot = Vec2::new(100.0, 100.0);
+
+        cycle.pick(spot, hits.clone());
+        assert_eq!(cycle.pick(spot + Vec2::splat(PICK_CYCLE_DISTANCE), hits.clone()), hits[0]);
+        cycle.pick(spot, hits.clone());
+        assert_eq!(cycle.pick(spot, vec![hits[1], hits[0]]), hits[1]);
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::picking::PickingBehavior;
+use bevy::prelude::{
+    Camera, Entity, GlobalTransform, Mesh3d, Vec2, Vec3, ViewVisibility, With, Without, World,
+};
+use bevy::render::primitives::Aabb;
+use transform_gizmo_bevy::GizmoTarget;
+use crate::camera::SdkCamera;
+use crate::editor_only::EditorOnly;
+use crate::selection::EditorSelection;
+
+/// Drags shorter than this, in egui points, are clicks, picked on release by `pick_system`.
+pub const MIN_DRAG_DISTANCE: f32 = 4.0;
+
+/// How a finished marquee changes [`EditorSelection`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MarqueeMode {
+    Replace,
+    /// Shift held.
+    Add,
+    /// Ctrl held.
+    Subtract,
+}
+
+/// Click-drag rectangle selection in the GameView tab.
+#[derive(Default)]
+pub struct Marquee {
+    start: Option<egui::Pos2>,
+}
+
+impl Marquee {
+    /// Senses drags over `viewport`, the rect the editor camera renders to, and draws the
+    /// rectangle while dragging. The response is marked changed when a drag changed the selection.
+    ///
+    /// Call it before the widgets drawn over the viewport, so those still get their clicks.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        world: &mut World,
+        viewport: egui::Rect,
+    ) -> egui::Response {
+        let mut response = ui.interact(viewport, ui.id().with("marquee"), egui::Sense::drag());
+        if self.drag(ui, world, viewport, &response) {
+            response.mark_changed();
+        }
+        response
+    }
+
+    /// Returns `true` when a finished drag changed the selection.
+    fn drag(
+        &mut self,
+        ui: &egui::Ui,
+        world: &mut World,
+        viewport: egui::Rect,
+        response: &egui::Response,
+    ) -> bool {
+        if response.drag_started_by(egui::PointerButton::Primary) && !gizmo_in_use(world) {
+            self.start = response.interact_pointer_pos();
+        }
+        let Some(start) = self.start else {
+            return false;
+        };
+        // A gizmo handle grabbed under the marquee wins, the drag is the gizmo's.
+        if gizmo_in_use(world) {
+            self.start = None;
+            return false;
+        }
+        let Some(current) = ui.ctx().pointer_latest_pos() else {
+            return false;
+        };
+        let rect = egui::Rect::from_two_pos(start, current).intersect(viewport);
+
+        if response.dragged() {
+            let stroke = ui.visuals().selection.stroke;
+            ui.painter().rect(rect, 0.0, stroke.color.gamma_multiply(0.1), stroke);
+            return false;
+        }
+
+        self.start = None;
+        if start.distance(current) < MIN_DRAG_DISTANCE {
+            return false;
+        }
+        let modifiers = ui.input(|input| input.modifiers);
+        let mode = if modifiers.ctrl {
+            MarqueeMode::Subtract
+        } else if modifiers.shift {
+            MarqueeMode::Add
+        } else {
+            MarqueeMode::Replace
+        };
+
+        let inside = entities_in_rect(world, viewport, rect);
+        let mut selection = world.resource_mut::<EditorSelection>();
+        match mode {
+            MarqueeMode::Replace => selection.set(inside),
+            MarqueeMode::Add => {
+                for entity in inside {
+                    selection.add(entity);
+                }
+            }
+            MarqueeMode::Subtract => {
+                for entity in inside {
+                    if selection.contains(entity) {
+                        selection.toggle(entity);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+fn gizmo_in_use(world: &mut World) -> bool {
+    world
+        .query::<&GizmoTarget>()
+        .iter(world)
+        .any(|target| target.is_focused() || target.is_active())
+}
+
+/// Pickable scene entities whose projected bounds lie entirely inside `rect`.
+/// Entities reaching behind the camera are never inside.
+fn entities_in_rect(world: &mut World, viewport: egui::Rect, rect: egui::Rect) -> Vec<Entity> {
+    let Ok((camera, camera_transform)) = world
+        .query_filtered::<(&Camera, &GlobalTransform), With<SdkCamera>>()
+        .get_single(world)
+    else {
+        return Vec::new();
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return Vec::new();
+    };
+    let (camera, camera_transform) = (camera.clone(), *camera_transform);
+    // Camera viewport coordinates are logical pixels, egui's are points.
+    let scale = Vec2::new(viewport.width(), viewport.height()) / viewport_size;
+
+    let mut candidates = world.query_filtered::<(
+        Entity,
+        &Aabb,
+        &GlobalTransform,
+        &ViewVisibility,
+        Option<&PickingBehavior>,
+    ), (With<Mesh3d>, Without<EditorOnly>)>();
+    candidates
+        .iter(world)
+        .filter(|(_, _, _, visibility, behavior)| {
+            visibility.get() && behavior.is_none_or(|behavior| behavior.is_hoverable)
+        })
+        .filter(|(_, aabb, transform, _, _)| {
+            let min = Vec3::from(aabb.min());
+            let max = Vec3::from(aabb.max());
+            (0..8).all(|corner| {
+                let local = Vec3::new(
+                    if corner & 1 == 0 { min.x } else { max.x },
+                    if corner & 2 == 0 { min.y } else { max.y },
+                    if corner & 4 == 0 { min.z } else { max.z },
+                );
+                camera
+                    .world_to_viewport(&camera_transform, transform.transform_point(local))
+                    .is_ok_and(|position| {
+                        let position = position * scale;
+                        rect.contains(viewport.min + egui::vec2(position.x, position.y))
+                    })
+            })
+        })
+        .map(|(entity, ..)| entity)
+        .collect()
+}
@@ -0,0 +1,169 @@
+use bevy::ecs::entity::EntityHashMap;
+use bevy::log::error;
+use bevy::prelude::{
+    DespawnRecursiveExt, DynamicScene, DynamicSceneBuilder, Entity, Fixed, NextState, Parent, Real,
+    Res, ResMut, Resource, State, States, Time, Virtual, With, World,
+};
+use transform_gizmo_bevy::GizmoTarget;
+use crate::editor_commands::HistoryManager;
+use crate::scene_file::scene_entities;
+use crate::selection::EditorSelection;
+
+/// Whether the scene is being edited or simulated.
+///
+/// Editing systems run only in `Edit`, gameplay systems from the `domain` crate only in `Play`
+/// and for single steps while `Paused`.
+#[derive(States, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EditorMode {
+    #[default]
+    Edit,
+    Play,
+    Paused,
+}
+
+/// Set while paused to run the gameplay systems for one frame.
+#[derive(Resource, Default)]
+pub struct PlayStep(pub bool);
+
+/// The edited scene from right before Play, restored by Stop.
+#[derive(Resource)]
+struct PlaySnapshot {
+    scene: DynamicScene,
+}
+
+/// Run condition for [`domain::GameplaySet`].
+pub fn gameplay_running(mode: Res<State<EditorMode>>, step: Res<PlayStep>) -> bool {
+    match mode.get() {
+        EditorMode::Edit => false,
+        EditorMode::Play => true,
+        EditorMode::Paused => step.0,
+    }
+}
+
+pub fn finish_step(mut step: ResMut<PlayStep>) {
+    step.0 = false;
+}
+
+/// Runs on the Edit → Play transition.
+pub fn snapshot_scene(world: &mut World) {
+    let entities = scene_entities(world);
+
+    // Gizmos edit the scene, which play mode must not do.
+    let targets: Vec<Entity> = world
+        .query_filtered::<Entity, With<GizmoTarget>>()
+        .iter(world)
+        .collect();
+    for entity in targets {
+        world.entity_mut(entity).remove::<GizmoTarget>();
+    }
+
+    // Unlike a saved level the snapshot stays in memory, so it keeps asset handles and every
+    // resource; only the clocks keep running across Stop.
+    let scene = DynamicSceneBuilder::from_world(world)
+        .deny_resource::<Time>()
+        .deny_resource::<Time<Real>>()
+        .deny_resource::<Time<Virtual>>()
+        .deny_resource::<Time<Fixed>>()
+        .extract_entities(entities.into_iter())
+        .extract_resources()
+        .build();
+    world.insert_resource(PlaySnapshot { scene });
+}
+
+/// Whether the scene is being edited rather than played. Changes only go into the undo history
+/// then, play mode works on a copy that Stop throws away.
+pub fn in_edit_mode(world: &World) -> bool {
+    *world.resource::<State<EditorMode>>().get() == EditorMode::Edit
+}
+
+/// Runs when returning to Edit. The scene is spawned as new entities; the undo history and the
+/// selection are remapped from the ids the entities had before Play.
+pub fn restore_scene(world: &mut World) {
+    let Some(snapshot) = world.remove_resource::<PlaySnapshot>() else {
+        return;
+    };
+
+    for entity in scene_entities(world) {
+        if world.get_entity(entity).is_ok() && world.get::<Parent>(entity).is_none() {
+            world.entity_mut(entity).despawn_recursive();
+        }
+    }
+
+    let mut entity_map = EntityHashMap::default();
+    if let Err(error) = snapshot.scene.write_to_world(world, &mut entity_map) {
+        error!("Failed to restore the scene after play: {error}");
+    }
+    world.resource_mut::<HistoryManager>().map_entities(&entity_map);
+    world.resource_mut::<EditorSelection>().map_entities(&entity_map);
+}
+
+/// Play, Pause, Step and Stop buttons for the menu bar.
+pub fn play_controls_ui(ui: &mut egui::Ui, world: &mut World) {
+    let mode = *world.resource::<State<EditorMode>>().get();
+    let mut next = None;
+
+    match mode {
+        EditorMode::Edit => {
+            if ui.button("Play").clicked() {
+                next = Some(EditorMode::Play);
+            }
+        }
+        EditorMode::Play => {
+            if ui.button("Pause").clicked() {
+                next = Some(EditorMode::Paused);
+            }
+        }
+        EditorMode::Paused => {
+            if ui.button("Resume").clicked() {
+                next = Some(EditorMode::Play);
+            }
+            if ui.button("Step").clicked() {
+                world.resource_mut::<PlayStep>().0 = true;
+            }
+        }
+    }
+    if mode != EditorMode::Edit && ui.button("Stop").clicked() {
+        next = Some(EditorMode::Edit);
+    }
+
+    if let Some(next) = next {
+        world.resource_mut::<NextState<EditorMode>>().set(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{default, AmbientLight, AppTypeRegistry, Handle, Mesh, Mesh3d, Transform};
+    use super::*;
+
+    #[test]
+    fn mesh_handles_and_resources_survive_play_and_stop() {
+        let mut world = World::new();
+        let type_registry = AppTypeRegistry::default();
+        {
+            let mut type_registry = type_registry.write();
+            type_registry.register::<Transform>();
+            type_registry.register::<Mesh3d>();
+            type_registry.register::<AmbientLight>();
+        }
+        world.insert_resource(type_registry);
+        world.insert_resource(HistoryManager::new());
+        world.init_resource::<EditorSelection>();
+        world.insert_resource(AmbientLight {
+            brightness: 100.0,
+            ..default()
+        });
+        let mesh = Handle::<Mesh>::weak_from_u128(7);
+        let entity = world.spawn((Transform::default(), Mesh3d(mesh.clone()))).id();
+
+        snapshot_scene(&mut world);
+        // What gameplay might do.
+        world.despawn(entity);
+        world.resource_mut::<AmbientLight>().brightness = 0.0;
+        restore_scene(&mut world);
+
+        let restored = world.query::<&Mesh3d>().single(&world);
+        assert_eq!(restored.0, mesh);
+        assert_eq!(world.resource::<AmbientLight>().brightness, 100.0);
+    }
+}
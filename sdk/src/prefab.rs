@@ -0,0 +1,888 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use bevy::asset::AssetId;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::event::EventCursor;
+use bevy::log::{info, warn};
+use bevy::prelude::{
+    AppTypeRegistry, AssetEvent, AssetServer, Assets, Children, DynamicScene, Entity, Events,
+    Handle, Local, Parent, ReflectComponent, Resource, World,
+};
+use bevy::reflect::{GetPath, PartialReflect, Reflect, ReflectFromReflect, ReflectMut, TypeRegistry};
+use bevy::scene::DynamicEntity;
+use bevy_inspector_egui::bevy_inspector::ui_for_value;
+use domain::{level_scene_builder, PrefabInstance, PrefabOverride, SceneGuid};
+use crate::command_record::CommandRecord;
+use crate::editor_commands::{
+    changed_fields, with_descendants, CommandError, CommandExecuted, CommandRedone, CommandUndone,
+    ComponentChange, EditorCommand, HistoryManager, PatchTarget, ReflectPatch, ReflectPatchCommand,
+    SetComponents,
+};
+use crate::editor_only::EditorOnly;
+use crate::entity_commands::SpawnEntity;
+use crate::scene_file::{deserialize_scene, file_path};
+
+/// Suggested location for new prefabs, relative to the assets folder. The `.scn.ron`
+/// extension lets the `AssetServer` load prefabs as `DynamicScene`s.
+pub const DEFAULT_PREFAB_PATH: &str = "prefabs/new.prefab.scn.ron";
+
+/// Prefabs used by the scene, kept loaded so instances follow changes to them.
+#[derive(Resource, Default)]
+pub struct PrefabLibrary {
+    handles: HashMap<String, Handle<DynamicScene>>,
+    /// Prefabs to instantiate once they are loaded.
+    pending: Vec<String>,
+}
+
+impl PrefabLibrary {
+    fn handle(&mut self, path: &str, asset_server: &AssetServer) -> Handle<DynamicScene> {
+        self.handles
+            .entry(path.to_string())
+            .or_insert_with(|| asset_server.load(path.to_string()))
+            .clone()
+    }
+
+    fn path(&self, id: AssetId<DynamicScene>) -> Option<&str> {
+        self.handles
+            .iter()
+            .find(|(_, handle)| handle.id() == id)
+            .map(|(path, _)| path.as_str())
+    }
+}
+
+/// Hierarchy links are per instance and never taken from the prefab.
+fn is_instance_data(type_id: TypeId) -> bool {
+    type_id == TypeId::of::<Parent>()
+        || type_id == TypeId::of::<Children>()
+        || type_id == TypeId::of::<PrefabInstance>()
+}
+
+/// Writes `root` and its descendants to `asset_path` and turns them into an instance of the new prefab.
+pub fn save_prefab(world: &mut World, root: Entity, asset_path: &Path) -> io::Result<()> {
+    let entities: Vec<Entity> = with_descendants(world, root)
+        .into_iter()
+        .filter(|entity| world.get::<EditorOnly>(*entity).is_none())
+        .collect();
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    let mut scene = level_scene_builder(world)
+        // Nested prefabs aren't supported, a saved instance becomes a plain copy.
+        .deny_component::<PrefabInstance>()
+        // Every instance gets GUIDs of its own.
+        .deny_component::<SceneGuid>()
+        .extract_entities(entities.iter().copied())
+        .build();
+    // The root's parent isn't part of the prefab.
+    if let Some(root) = scene.entities.iter_mut().find(|entity| entity.entity == root) {
+        root.components.retain(|component| {
+            component
+                .get_represented_type_info()
+                .is_none_or(|info| info.type_id() != TypeId::of::<Parent>())
+        });
+    }
+
+    let contents = scene
+        .serialize(&type_registry)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    let path = file_path(asset_path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, contents)?;
+
+    let prefab = asset_path.to_string_lossy().into_owned();
+    let changes = entities
+        .into_iter()
+        .map(|entity| ComponentChange {
+            entity,
+            type_id: TypeId::of::<PrefabInstance>(),
+            before: world
+                .get::<PrefabInstance>(entity)
+                .map(|instance| Box::new(instance.clone()) as Box<dyn PartialReflect>),
+            after: Some(Box::new(PrefabInstance {
+                prefab: prefab.clone(),
+                source: entity.to_bits(),
+                overrides: Vec::new(),
+            })),
+        })
+        .collect();
+    let command = SetComponents {
+        label: format!("Save prefab {prefab}"),
+        changes,
+    };
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        history.execute(Box::new(command), world)
+    })
+    .map_err(io::Error::other)?;
+
+    let asset_server = world.resource::<AssetServer>().clone();
+    let mut library = world.resource_mut::<PrefabLibrary>();
+    if library.handles.contains_key(&prefab) {
+        asset_server.reload(prefab);
+    } else {
+        library.handle(&prefab, &asset_server);
+    }
+    info!("Saved prefab to {}", path.display());
+    Ok(())
+}
+
+/// Spawns an instance of the prefab at `asset_path` as soon as it is loaded.
+pub fn instantiate_prefab(world: &mut World, asset_path: &Path) {
+    let prefab = asset_path.to_string_lossy().into_owned();
+    let asset_server = world.resource::<AssetServer>().clone();
+    let mut library = world.resource_mut::<PrefabLibrary>();
+    library.handle(&prefab, &asset_server);
+    library.pending.push(prefab);
+}
+
+fn spawn_instance(
+    world: &mut World,
+    prefab: &str,
+    handle: &Handle<DynamicScene>,
+) -> Result<Entity, CommandError> {
+    let mut entity_map = EntityHashMap::default();
+    world.resource_scope::<Assets<DynamicScene>, _>(|world, scenes| {
+        let scene = scenes
+            .get(handle)
+            .ok_or_else(|| CommandError::Scene(format!("Prefab {prefab} isn't loaded")))?;
+        scene
+            .write_to_world(world, &mut entity_map)
+            .map_err(|error| CommandError::Scene(error.to_string()))
+    })?;
+
+    for (source, entity) in &entity_map {
+        world.entity_mut(*entity).insert(PrefabInstance {
+            prefab: prefab.to_string(),
+            source: source.to_bits(),
+            overrides: Vec::new(),
+        });
+    }
+    entity_map
+        .values()
+        .copied()
+        .find(|entity| world.get::<Parent>(*entity).is_none())
+        .ok_or_else(|| CommandError::Scene(format!("Prefab {prefab} has no root entity")))
+}
+
+/// Instantiates loaded prefabs, makes instances follow changes to their prefab and keeps
+/// their overrides up to date after edits.
+pub fn update_prefab_instances(
+    world: &mut World,
+    mut asset_events: Local<EventCursor<AssetEvent<DynamicScene>>>,
+    mut executed: Local<EventCursor<CommandExecuted>>,
+    mut undone: Local<EventCursor<CommandUndone>>,
+    mut redone: Local<EventCursor<CommandRedone>>,
+) {
+    let asset_server = world.resource::<AssetServer>().clone();
+
+    // Instances from an opened scene need their prefab loaded to follow it.
+    let used: Vec<String> = world
+        .query::<&PrefabInstance>()
+        .iter(world)
+        .map(|instance| instance.prefab.clone())
+        .collect();
+    let mut library = world.resource_mut::<PrefabLibrary>();
+    for prefab in used {
+        library.handle(&prefab, &asset_server);
+    }
+
+    let pending = std::mem::take(&mut world.resource_mut::<PrefabLibrary>().pending);
+    for prefab in pending {
+        let handle = world.resource_mut::<PrefabLibrary>().handle(&prefab, &asset_server);
+        if asset_server.is_loaded_with_dependencies(&handle) {
+            match spawn_instance(world, &prefab, &handle) {
+                Ok(root) => world
+                    .resource_mut::<HistoryManager>()
+                    .push(Box::new(SpawnEntity::spawned(root))),
+                Err(error) => warn!("Failed to instantiate {prefab}: {error}"),
+            }
+        } else if asset_server.load_state(&handle).is_failed() {
+            warn!("Failed to load prefab {prefab}");
+        } else {
+            world.resource_mut::<PrefabLibrary>().pending.push(prefab);
+        }
+    }
+
+    // The first load only catches instances up with edits made to the prefab since the scene
+    // was saved, which isn't a step to undo. Changes to a loaded prefab are.
+    let mut changed: HashMap<String, bool> = HashMap::new();
+    for event in asset_events.read(world.resource::<Events<AssetEvent<DynamicScene>>>()) {
+        let (id, record) = match event {
+            AssetEvent::LoadedWithDependencies { id } => (*id, false),
+            AssetEvent::Modified { id } => (*id, true),
+            _ => continue,
+        };
+        if let Some(prefab) = world.resource::<PrefabLibrary>().path(id) {
+            *changed.entry(prefab.to_string()).or_default() |= record;
+        }
+    }
+    for (prefab, record) in changed {
+        sync_instances(world, &prefab, record);
+    }
+
+    let mut edited: Vec<Entity> = Vec::new();
+    edited.extend(
+        executed
+            .read(world.resource::<Events<CommandExecuted>>())
+            .flat_map(|event| event.0.entities.iter().copied()),
+    );
+    edited.extend(
+        undone
+            .read(world.resource::<Events<CommandUndone>>())
+            .flat_map(|event| event.0.entities.iter().copied()),
+    );
+    edited.extend(
+        redone
+            .read(world.resource::<Events<CommandRedone>>())
+            .flat_map(|event| event.0.entities.iter().copied()),
+    );
+    for entity in edited {
+        refresh_overrides(world, entity);
+    }
+}
+
+/// Source entity data of an instance, if its prefab is loaded.
+fn with_source<R>(
+    world: &mut World,
+    entity: Entity,
+    f: impl FnOnce(&mut World, &PrefabInstance, &DynamicEntity, &TypeRegistry) -> R,
+) -> Option<R> {
+    let instance = world.get::<PrefabInstance>(entity)?.clone();
+    let handle = world
+        .resource::<PrefabLibrary>()
+        .handles
+        .get(&instance.prefab)?
+        .clone();
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    world.resource_scope::<Assets<DynamicScene>, _>(|world, scenes| {
+        let source = scenes
+            .get(&handle)?
+            .entities
+            .iter()
+            .find(|source| source.entity.to_bits() == instance.source)?;
+        Some(f(world, &instance, source, &type_registry))
+    })
+}
+
+/// Changes that bring every instance of `prefab` in line with it, keeping overridden fields.
+/// Components an instance lacks are inserted.
+fn instance_changes(world: &mut World, prefab: &str) -> Vec<ComponentChange> {
+    let instances: Vec<Entity> = world
+        .query::<(Entity, &PrefabInstance)>()
+        .iter(world)
+        .filter(|(_, instance)| instance.prefab == prefab)
+        .map(|(entity, _)| entity)
+        .collect();
+
+    let mut changes = Vec::new();
+    for entity in instances {
+        let entity_changes = with_source(world, entity, |world, instance, source, type_registry| {
+            let mut changes = Vec::new();
+            for prefab_value in &source.components {
+                let Some(info) = prefab_value.get_represented_type_info() else {
+                    continue;
+                };
+                if is_instance_data(info.type_id()) {
+                    continue;
+                }
+                let Some(reflect_component) = type_registry
+                    .get(info.type_id())
+                    .and_then(|registration| registration.data::<ReflectComponent>())
+                else {
+                    continue;
+                };
+                let overrides: Vec<&PrefabOverride> = instance
+                    .overrides
+                    .iter()
+                    .filter(|o| o.component == info.type_path())
+                    .collect();
+                if overrides.iter().any(|o| o.path.is_empty()) {
+                    continue;
+                }
+
+                let mut value = prefab_value.clone_value();
+                let current = reflect_component.reflect(world.entity(entity));
+                if let Some(current) = current {
+                    for o in &overrides {
+                        copy_field(current.as_partial_reflect(), value.as_mut(), &o.path);
+                    }
+                    if current.reflect_partial_eq(value.as_ref()) == Some(true) {
+                        continue;
+                    }
+                }
+                changes.push(ComponentChange {
+                    entity,
+                    type_id: info.type_id(),
+                    before: current.map(|current| current.as_partial_reflect().clone_value()),
+                    after: Some(value),
+                });
+            }
+            changes
+        });
+        changes.extend(entity_changes.into_iter().flatten());
+    }
+    changes
+}
+
+/// Applies the prefab to every instance of it, keeping overridden fields. With `record` the
+/// update is one undo step, otherwise it is applied without touching the history.
+fn sync_instances(world: &mut World, prefab: &str, record: bool) {
+    let changes = instance_changes(world, prefab);
+    if changes.is_empty() {
+        return;
+    }
+    let mut command = SetComponents {
+        label: format!("Update instances of {prefab}"),
+        changes,
+    };
+    let result = if record {
+        world.resource_scope::<HistoryManager, _>(|world, mut history| {
+            history.execute(Box::new(command), world)
+        })
+    } else {
+        let result = command.execute(world);
+        // Without the history no CommandExecuted is sent to refresh them.
+        for entity in command.entities() {
+            refresh_overrides(world, entity);
+        }
+        result
+    };
+    if let Err(error) = result {
+        warn!("Failed to update instances of {prefab}: {error}");
+    }
+}
+
+/// Recomputes which fields of an instance differ from its prefab.
+fn refresh_overrides(world: &mut World, entity: Entity) {
+    let overrides = with_source(world, entity, |world, _, source, type_registry| {
+        let mut overrides = Vec::new();
+        for prefab_value in &source.components {
+            let Some(info) = prefab_value.get_represented_type_info() else {
+                continue;
+            };
+            if is_instance_data(info.type_id()) {
+                continue;
+            }
+            let Some(current) = type_registry
+                .get(info.type_id())
+                .and_then(|registration| registration.data::<ReflectComponent>())
+                .and_then(|reflect_component| reflect_component.reflect(world.entity(entity)))
+            else {
+                continue;
+            };
+            for path in changed_fields(prefab_value.as_ref(), current.as_partial_reflect()) {
+                overrides.push(PrefabOverride {
+                    component: info.type_path().to_string(),
+                    path,
+                });
+            }
+        }
+        overrides
+    });
+
+    let Some(overrides) = overrides else {
+        return;
+    };
+    let Some(mut instance) = world.get_mut::<PrefabInstance>(entity) else {
+        return;
+    };
+    if instance.overrides != overrides {
+        instance.overrides = overrides;
+    }
+}
+
+/// Copies the field at `path` (or the whole value for an empty path) from `from` to `to`.
+fn copy_field(from: &dyn PartialReflect, to: &mut dyn PartialReflect, path: &str) -> bool {
+    if path.is_empty() {
+        return to.try_apply(from).is_ok();
+    }
+    let (Ok(source), Ok(target)) = (from.reflect_path(path), to.reflect_path_mut(path)) else {
+        return false;
+    };
+    target.try_apply(source).is_ok()
+}
+
+/// Resets the given overrides of an instance (all of them for `None`) as one undo step.
+pub fn revert_to_prefab(world: &mut World, entity: Entity, only: Option<&PrefabOverride>) {
+    let patches = with_source(world, entity, |world, instance, source, type_registry| {
+        let mut patches = Vec::new();
+        for prefab_value in &source.components {
+            let Some(info) = prefab_value.get_represented_type_info() else {
+                continue;
+            };
+            let paths: Vec<&str> = instance
+                .overrides
+                .iter()
+                .filter(|o| o.component == info.type_path() && only.is_none_or(|only| *o == only))
+                .map(|o| o.path.as_str())
+                .collect();
+            if paths.is_empty() {
+                continue;
+            }
+            let Some(current) = type_registry
+                .get(info.type_id())
+                .and_then(|registration| registration.data::<ReflectComponent>())
+                .and_then(|reflect_component| reflect_component.reflect(world.entity(entity)))
+            else {
+                continue;
+            };
+
+            let before = current.as_partial_reflect().clone_value();
+            let mut after = current.as_partial_reflect().clone_value();
+            for path in paths {
+                copy_field(prefab_value.as_ref(), after.as_mut(), path);
+            }
+            patches.push(ReflectPatch::new(
+                PatchTarget::Component(entity, info.type_id()),
+                before,
+                after,
+                type_registry,
+            ));
+        }
+        patches
+    });
+
+    let Some(patches) = patches.filter(|patches| !patches.is_empty()) else {
+        return;
+    };
+    let command = ReflectPatchCommand {
+        label: format!("Revert {entity} to prefab"),
+        patches,
+    };
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        if let Err(error) = history.execute(Box::new(command), world) {
+            warn!("Failed to revert {entity} to prefab: {error}");
+        }
+    });
+}
+
+/// Writes the contents of a prefab file and replaces the loaded prefab with them.
+fn write_prefab(world: &mut World, prefab: &str, contents: &str) -> Result<(), CommandError> {
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let scene = deserialize_scene(contents, &type_registry.read())
+        .map_err(|error| CommandError::Scene(error.to_string()))?;
+    std::fs::write(file_path(Path::new(prefab)), contents)
+        .map_err(|error| CommandError::Io(error.to_string()))?;
+
+    let asset_server = world.resource::<AssetServer>().clone();
+    let handle = world.resource_mut::<PrefabLibrary>().handle(prefab, &asset_server);
+    world.resource_mut::<Assets<DynamicScene>>().insert(&handle, scene);
+    Ok(())
+}
+
+/// Writes overrides of an instance into its prefab and updates the other instances.
+/// Undo writes the previous prefab back to the file.
+pub struct ApplyToPrefab {
+    pub prefab: String,
+    /// The applied instance. Its overrides are recomputed after each run, as the applied
+    /// fields no longer differ from the prefab.
+    pub source: Entity,
+    /// The prefab file contents before and after.
+    pub before: String,
+    pub after: String,
+    /// The instance updates, made on the first run and replayed on redo.
+    pub instances: Option<Box<dyn EditorCommand>>,
+}
+
+impl ApplyToPrefab {
+    /// Applies the given overrides of `entity` (all of them for `None`).
+    pub fn new(world: &World, entity: Entity, only: Option<&PrefabOverride>) -> Result<Self, CommandError> {
+        let instance = world
+            .get::<PrefabInstance>(entity)
+            .cloned()
+            .ok_or(CommandError::MissingComponent {
+                entity,
+                component: std::any::type_name::<PrefabInstance>(),
+            })?;
+        let not_loaded = || CommandError::Scene(format!("Prefab {} isn't loaded", instance.prefab));
+        let handle = world
+            .resource::<PrefabLibrary>()
+            .handles
+            .get(&instance.prefab)
+            .ok_or_else(not_loaded)?;
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        let type_registry = type_registry.read();
+
+        let before = world
+            .resource::<Assets<DynamicScene>>()
+            .get(handle)
+            .ok_or_else(not_loaded)?
+            .serialize(&type_registry)
+            .map_err(|error| CommandError::Scene(error.to_string()))?;
+        let mut scene = deserialize_scene(&before, &type_registry)
+            .map_err(|error| CommandError::Scene(error.to_string()))?;
+        let source = scene
+            .entities
+            .iter_mut()
+            .find(|source| source.entity.to_bits() == instance.source)
+            .ok_or_else(|| CommandError::Scene(format!("{entity} isn't part of {}", instance.prefab)))?;
+
+        for prefab_value in &mut source.components {
+            let Some(info) = prefab_value.get_represented_type_info() else {
+                continue;
+            };
+            let Some(current) = type_registry
+                .get(info.type_id())
+                .and_then(|registration| registration.data::<ReflectComponent>())
+                .and_then(|reflect_component| reflect_component.reflect(world.entity(entity)))
+            else {
+                continue;
+            };
+            let type_path = info.type_path();
+            for o in instance
+                .overrides
+                .iter()
+                .filter(|o| o.component == type_path && only.is_none_or(|only| *o == only))
+            {
+                copy_field(current.as_partial_reflect(), prefab_value.as_mut(), &o.path);
+            }
+        }
+        let after = scene
+            .serialize(&type_registry)
+            .map_err(|error| CommandError::Scene(error.to_string()))?;
+
+        Ok(Self {
+            prefab: instance.prefab,
+            source: entity,
+            before,
+            after,
+            instances: None,
+        })
+    }
+}
+
+impl EditorCommand for ApplyToPrefab {
+    fn label(&self) -> String {
+        format!("Apply to prefab {}", self.prefab)
+    }
+
+    fn entities(&self) -> Vec<Entity> {
+        let mut entities = vec![self.source];
+        if let Some(instances) = &self.instances {
+            entities.extend(instances.entities().into_iter().filter(|entity| *entity != self.source));
+        }
+        entities
+    }
+
+    fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.before.len()
+            + self.after.len()
+            + self.instances.as_ref().map_or(0, |instances| instances.size_bytes())
+    }
+
+    fn record(&self, type_registry: &TypeRegistry) -> Option<CommandRecord> {
+        Some(CommandRecord::ApplyToPrefab {
+            prefab: self.prefab.clone(),
+            source: self.source.to_bits(),
+            before: self.before.clone(),
+            after: self.after.clone(),
+            instances: match &self.instances {
+                Some(instances) => Some(Box::new(instances.record(type_registry)?)),
+                None => None,
+            },
+        })
+    }
+
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        write_prefab(world, &self.prefab, &self.after)?;
+        match &mut self.instances {
+            Some(instances) => instances.execute(world)?,
+            None => {
+                let mut instances = SetComponents {
+                    label: format!("Update instances of {}", self.prefab),
+                    changes: instance_changes(world, &self.prefab),
+                };
+                instances.execute(world)?;
+                self.instances = Some(Box::new(instances));
+            }
+        }
+        refresh_overrides(world, self.source);
+        Ok(())
+    }
+
+    fn undo(&mut self, world: &mut World) -> Result<(), CommandError> {
+        if let Some(instances) = &mut self.instances {
+            instances.undo(world)?;
+        }
+        write_prefab(world, &self.prefab, &self.before)?;
+        refresh_overrides(world, self.source);
+        Ok(())
+    }
+
+    fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        self.source = entity_map.get(&self.source).copied().unwrap_or(self.source);
+        if let Some(instances) = &mut self.instances {
+            instances.map_entities(entity_map);
+        }
+    }
+}
+
+/// Writes the given overrides of an instance (all of them for `None`) into the prefab as one
+/// undo step, which then updates every other instance.
+pub fn apply_to_prefab(world: &mut World, entity: Entity, only: Option<&PrefabOverride>) {
+    let result = ApplyToPrefab::new(world, entity, only).and_then(|command| {
+        world.resource_scope::<HistoryManager, _>(|world, mut history| {
+            history.execute(Box::new(command), world)
+        })
+    });
+    if let Err(error) = result {
+        warn!("Failed to apply {entity} to its prefab: {error}");
+    }
+}
+
+/// Revert or apply picked in [`prefab_inspector_ui`]. It is carried out by [`Self::apply`]
+/// after the inspector pass, so the inspector snapshot doesn't record the change a second time.
+pub enum PrefabEdit {
+    /// Resets the given override, or all of them for `None`.
+    Revert(Option<PrefabOverride>),
+    /// Writes the given override into the prefab, or all of them for `None`.
+    Apply(Option<PrefabOverride>),
+}
+
+impl PrefabEdit {
+    pub fn apply(self, world: &mut World, entity: Entity) {
+        match self {
+            PrefabEdit::Revert(only) => revert_to_prefab(world, entity, only.as_ref()),
+            PrefabEdit::Apply(only) => apply_to_prefab(world, entity, only.as_ref()),
+        }
+    }
+}
+
+fn overridden_text(ui: &egui::Ui, text: &str) -> egui::RichText {
+    egui::RichText::new(format!("● {text}"))
+        .strong()
+        .color(ui.visuals().warn_fg_color)
+}
+
+/// Inspector for a prefab instance: its components, with the overridden fields marked.
+/// Right-clicking a marked field reverts it or applies it to the prefab.
+pub fn prefab_inspector_ui(ui: &mut egui::Ui, world: &mut World, entity: Entity) -> Option<PrefabEdit> {
+    let instance = world.get::<PrefabInstance>(entity)?.clone();
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let mut edit = None;
+
+    ui.horizontal(|ui| {
+        ui.label(format!("Prefab: {}", instance.prefab));
+        let any = !instance.overrides.is_empty();
+        if ui.add_enabled(any, egui::Button::new("Revert all")).clicked() {
+            edit = Some(PrefabEdit::Revert(None));
+        }
+        if ui.add_enabled(any, egui::Button::new("Apply all")).clicked() {
+            edit = Some(PrefabEdit::Apply(None));
+        }
+    });
+    ui.separator();
+
+    let entity_ref = world.entity(entity);
+    let components: Vec<TypeId> = entity_ref
+        .archetype()
+        .components()
+        .filter_map(|id| world.components().get_info(id)?.type_id())
+        .filter(|type_id| !is_instance_data(*type_id))
+        .collect();
+
+    for type_id in components {
+        let (reflect_component, from_reflect, type_path, name) = {
+            let type_registry = type_registry.read();
+            let Some(registration) = type_registry.get(type_id) else {
+                continue;
+            };
+            let (Some(reflect_component), Some(from_reflect)) = (
+                registration.data::<ReflectComponent>(),
+                registration.data::<ReflectFromReflect>(),
+            ) else {
+                continue;
+            };
+            let type_path_table = registration.type_info().type_path_table();
+            (
+                reflect_component.clone(),
+                from_reflect.clone(),
+                type_path_table.path(),
+                type_path_table.short_path(),
+            )
+        };
+        let Some(mut value) = reflect_component
+            .reflect(world.entity(entity))
+            .and_then(|current| from_reflect.from_reflect(current.as_partial_reflect()))
+        else {
+            continue;
+        };
+
+        let overrides: Vec<&PrefabOverride> = instance
+            .overrides
+            .iter()
+            .filter(|o| o.component == type_path)
+            .collect();
+        let header = match overrides.is_empty() {
+            true => egui::RichText::new(name),
+            false => overridden_text(ui, name),
+        };
+        let id = ui.id().with((entity, type_id));
+        let changed = egui::CollapsingHeader::new(header)
+            .id_salt(id)
+            .show(ui, |ui| fields_ui(ui, world, value.as_mut(), id, &overrides, &mut edit))
+            .body_returned
+            .unwrap_or(false);
+        if changed {
+            reflect_component.apply(&mut world.entity_mut(entity), value.as_partial_reflect());
+        }
+    }
+    edit
+}
+
+/// One row per field for structs, the whole value otherwise. Returns whether anything changed.
+fn fields_ui(
+    ui: &mut egui::Ui,
+    world: &mut World,
+    value: &mut dyn Reflect,
+    id: egui::Id,
+    overrides: &[&PrefabOverride],
+    edit: &mut Option<PrefabEdit>,
+) -> bool {
+    let mut changed = false;
+    match value.reflect_mut() {
+        ReflectMut::Struct(value) => {
+            egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+                for index in 0..value.field_len() {
+                    let name = value.name_at(index).unwrap_or_default().to_string();
+                    let path = format!(".{name}");
+                    if let Some(field) = value.field_at_mut(index) {
+                        changed |= field_ui(ui, world, &name, &path, field, overrides, edit);
+                    }
+                    ui.end_row();
+                }
+            });
+        }
+        ReflectMut::TupleStruct(value) => {
+            egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+                for index in 0..value.field_len() {
+                    let path = format!(".{index}");
+                    if let Some(field) = value.field_mut(index) {
+                        changed |= field_ui(ui, world, &index.to_string(), &path, field, overrides, edit);
+                    }
+                    ui.end_row();
+                }
+            });
+        }
+        // Differences in other kinds of values override the whole component, see `changed_fields`.
+        _ => changed = ui_for_value(value, ui, world),
+    }
+    changed
+}
+
+fn field_ui(
+    ui: &mut egui::Ui,
+    world: &mut World,
+    name: &str,
+    path: &str,
+    field: &mut dyn PartialReflect,
+    overrides: &[&PrefabOverride],
+    edit: &mut Option<PrefabEdit>,
+) -> bool {
+    match overrides.iter().find(|o| o.path == path) {
+        Some(o) => {
+            ui.label(overridden_text(ui, name))
+                .on_hover_text("Overridden on this instance, right-click to revert or apply")
+                .context_menu(|ui| {
+                    if ui.button("Revert to prefab").clicked() {
+                        *edit = Some(PrefabEdit::Revert(Some((*o).clone())));
+                        ui.close_menu();
+                    }
+                    if ui.button("Apply to prefab").clicked() {
+                        *edit = Some(PrefabEdit::Apply(Some((*o).clone())));
+                        ui.close_menu();
+                    }
+                });
+        }
+        None => {
+            ui.label(name);
+        }
+    }
+    match field.try_as_reflect_mut() {
+        Some(field) => ui_for_value(field, ui, world),
+        None => {
+            ui.label("(not editable)");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{App, AssetApp, AssetPlugin, MinimalPlugins, Transform};
+    use super::*;
+
+    const PREFAB: &str = "prefabs/apply_to_prefab_test.prefab.scn.ron";
+
+    fn translation_x(world: &World, entity: Entity) -> f32 {
+        world.get::<Transform>(entity).unwrap().translation.x
+    }
+
+    fn override_count(world: &World, entity: Entity) -> usize {
+        world.get::<PrefabInstance>(entity).unwrap().overrides.len()
+    }
+
+    fn move_and_apply(world: &mut World, entity: Entity, x: f32) {
+        world.get_mut::<Transform>(entity).unwrap().translation.x = x;
+        refresh_overrides(world, entity);
+        apply_to_prefab(world, entity, None);
+    }
+
+    #[test]
+    fn applied_overrides_are_cleared_so_later_applies_reach_the_instance() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<DynamicScene>()
+            .init_resource::<PrefabLibrary>()
+            .insert_resource(HistoryManager::new())
+            .register_type::<Transform>()
+            .register_type::<PrefabInstance>();
+        let world = app.world_mut();
+        std::fs::create_dir_all(file_path(Path::new(PREFAB)).parent().unwrap()).unwrap();
+
+        let source = Entity::from_raw(0);
+        let scene = DynamicScene {
+            resources: Vec::new(),
+            entities: vec![DynamicEntity {
+                entity: source,
+                components: vec![Box::new(Transform::IDENTITY)],
+            }],
+        };
+        let handle = world.resource_mut::<Assets<DynamicScene>>().add(scene);
+        world
+            .resource_mut::<PrefabLibrary>()
+            .handles
+            .insert(PREFAB.to_string(), handle);
+        let [first, second] = [(); 2].map(|()| {
+            let instance = PrefabInstance {
+                prefab: PREFAB.to_string(),
+                source: source.to_bits(),
+                overrides: Vec::new(),
+            };
+            world.spawn((Transform::IDENTITY, instance)).id()
+        });
+
+        move_and_apply(world, first, 1.0);
+        assert_eq!(override_count(world, first), 0);
+        assert_eq!(translation_x(world, second), 1.0);
+
+        move_and_apply(world, second, 2.0);
+        assert_eq!(override_count(world, second), 0);
+        assert_eq!(translation_x(world, first), 2.0);
+
+        // Undo puts the old prefab back, so the second instance differs from it again.
+        world
+            .resource_scope::<HistoryManager, _>(|world, mut history| history.undo(world))
+            .unwrap();
+        assert_eq!(translation_x(world, first), 1.0);
+        assert_eq!(override_count(world, second), 1);
+
+        std::fs::remove_file(file_path(Path::new(PREFAB))).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use bevy::asset::io::file::FileAssetReader;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::system::SystemIdMarker;
+use bevy::log::{info, warn};
+use bevy::picking::pointer::PointerId;
+use bevy::prelude::{
+    AmbientLight, AppTypeRegistry, AssetServer, BuildChildrenTransformExt, Children, Commands,
+    DespawnRecursiveExt, DynamicScene, DynamicSceneRoot, Entity, Observer, Parent, Query, Resource,
+    SceneRoot, Trigger, Without, World,
+};
+use bevy::reflect::TypeRegistry;
+use bevy::scene::serde::SceneDeserializer;
+use bevy::scene::SceneInstanceReady;
+use bevy::utils::HashMap;
+use bevy::window::{Monitor, Window};
+use domain::{extract_level, LevelInfo, SceneGuid, LEVEL_VERSION};
+use serde::de::DeserializeSeed;
+use crate::camera::move_to_camera_start;
+use crate::command_record::{serialize_value, HistoryRecord};
+use crate::editor_commands::HistoryManager;
+use crate::editor_only::EditorOnly;
+use crate::selection::EditorSelection;
+
+/// Suggested location for new scenes, relative to the assets folder.
+pub const DEFAULT_SCENE_PATH: &str = "scenes/level.scn.ron";
+
+/// The scene being edited, as an asset path relative to the assets folder.
+/// `None` until the scene is saved or opened.
+#[derive(Resource, Default)]
+pub struct CurrentScene {
+    pub path: Option<PathBuf>,
+    /// Counts the saves of the scene, stored in its history file so a recovery written
+    /// before the latest save can be told apart from unsaved work on top of it.
+    pub generation: u64,
+}
+
+/// Where an asset path lives on disk, so saved scenes can be loaded back through the `AssetServer`.
+pub fn file_path(asset_path: &Path) -> PathBuf {
+    FileAssetReader::get_base_path().join("assets").join(asset_path)
+}
+
+/// Query filter for the entities that make up the scene: everything except editor tooling,
+/// the engine's own entities (windows, monitors, pointers, observers, one-shot systems) and
+/// the roots that are still loading a scene in.
+pub type SceneContent = (
+    Without<EditorOnly>,
+    Without<Window>,
+    Without<Monitor>,
+    Without<PointerId>,
+    Without<Observer>,
+    Without<SystemIdMarker>,
+    Without<DynamicSceneRoot>,
+    Without<SceneRoot>,
+);
+
+/// Entities that make up the scene, see [`SceneContent`].
+pub fn scene_entities(world: &mut World) -> Vec<Entity> {
+    world
+        .query_filtered::<Entity, SceneContent>()
+        .iter(world)
+        .collect()
+}
+
+/// The scene as the RON written by [`save_scene`].
+pub fn serialize_scene(world: &mut World) -> io::Result<String> {
+    let entities = scene_entities(world);
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    // Saving upgrades the level to the format of this build.
+    world.get_resource_or_init::<LevelInfo>().version = LEVEL_VERSION;
+    let mut scene = extract_level(world, entities.into_iter());
+    retain_serializable(&mut scene, &type_registry);
+
+    scene
+        .serialize(&type_registry)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Reads a scene written by [`serialize_scene`] or [`DynamicScene::serialize`].
+pub fn deserialize_scene(contents: &str, type_registry: &TypeRegistry) -> io::Result<DynamicScene> {
+    let mut deserializer = ron::de::Deserializer::from_str(contents)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    SceneDeserializer { type_registry }
+        .deserialize(&mut deserializer)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// The undo history along with the [`SceneGuid`]s needed to restore it after a reload.
+pub fn history_record(world: &mut World) -> HistoryRecord {
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let mut record = world.resource::<HistoryManager>().to_record(&type_registry.read());
+    record.guids = world
+        .query::<(Entity, &SceneGuid)>()
+        .iter(world)
+        .map(|(entity, guid)| (entity.to_bits(), guid.0.as_u128()))
+        .collect();
+    record.generation = world.resource::<CurrentScene>().generation;
+    record
+}
+
+/// Writes the scene to `asset_path` as RON, with the undo history next to it unless
+/// [`HistoryManager::save_with_scene`] is off.
+pub fn save_scene(world: &mut World, asset_path: &Path) -> io::Result<()> {
+    let contents = serialize_scene(world)?;
+    let path = file_path(asset_path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, contents)?;
+
+    // Past any earlier save to this file, in case it was last saved from another scene.
+    let saved = HistoryRecord::load(&path).map_or(0, |history| history.generation);
+    let mut current_scene = world.resource_mut::<CurrentScene>();
+    current_scene.generation = current_scene.generation.max(saved) + 1;
+    if world.resource::<HistoryManager>().save_with_scene {
+        if let Err(error) = history_record(world).save(&path) {
+            warn!("Failed to save history for {}: {error}", path.display());
+        }
+    } else if let Err(error) = HistoryRecord::remove(&path) {
+        // A history left from an earlier save wouldn't match the scene anymore.
+        warn!("Failed to remove the old history for {}: {error}", path.display());
+    }
+    world.resource_mut::<HistoryManager>().mark_saved();
+    world.resource_mut::<CurrentScene>().path = Some(asset_path.to_path_buf());
+    info!("Saved scene to {}", path.display());
+    Ok(())
+}
+
+/// Drops components that can't round-trip (e.g. components holding handles), so they're
+/// left out rather than failing the whole scene.
+pub fn retain_serializable(scene: &mut DynamicScene, type_registry: &TypeRegistry) {
+    for entity in &mut scene.entities {
+        entity.components.retain(|component| {
+            let serializable = serialize_value(component.as_ref(), type_registry).is_some();
+            if !serializable {
+                let type_path = component
+                    .get_represented_type_info()
+                    .map_or(component.reflect_type_path(), |info| info.type_path());
+                warn!("Leaving out {type_path} on {}, it can't be serialized", entity.entity);
+            }
+            serializable
+        });
+    }
+}
+
+/// Despawns the scene and forgets the selection and the undo history that refer to it.
+pub fn clear_scene(world: &mut World) {
+    let entities = scene_entities(world);
+    for entity in entities {
+        if world.get::<Parent>(entity).is_none() {
+            world.entity_mut(entity).despawn_recursive();
+        }
+    }
+    world.resource_mut::<EditorSelection>().clear();
+    world.resource_mut::<HistoryManager>().clear();
+}
+
+/// Replaces the current scene with the one at `asset_path`. Loading finishes in a later frame,
+/// the history saved with the scene is restored then.
+pub fn open_scene(world: &mut World, asset_path: &Path) {
+    clear_scene(world);
+
+    let scene = world.resource::<AssetServer>().load(asset_path.to_path_buf());
+    world.spawn(DynamicSceneRoot(scene)).observe(flatten_loaded_scene);
+    *world.resource_mut::<CurrentScene>() = CurrentScene {
+        path: Some(asset_path.to_path_buf()),
+        generation: 0,
+    };
+}
+
+/// Replaces the current scene with a copy of the template at `template`, an asset path.
+/// The new scene has no path until it's saved.
+pub fn new_scene(world: &mut World, template: &Path) {
+    clear_scene(world);
+    // Templates that don't set these get the defaults rather than the previous scene's.
+    world.insert_resource(LevelInfo::default());
+    world.insert_resource(AmbientLight::default());
+
+    // Parsed from a string, so templates can name their asset source, e.g. `user_templates://`.
+    let scene = world.resource::<AssetServer>().load(template.to_string_lossy().into_owned());
+    world.spawn(DynamicSceneRoot(scene)).observe(flatten_loaded_scene);
+    *world.resource_mut::<CurrentScene>() = CurrentScene::default();
+}
+
+/// Moves the loaded entities out from under the `DynamicSceneRoot`, so the scene's own
+/// top-level entities stay top-level and the next save looks like the file that was opened.
+fn flatten_loaded_scene(
+    trigger: Trigger<SceneInstanceReady>,
+    children: Query<&Children>,
+    mut commands: Commands,
+) {
+    let root = trigger.entity();
+    if let Ok(children) = children.get(root) {
+        for child in children.iter() {
+            commands.entity(*child).remove_parent_in_place();
+        }
+    }
+    commands.entity(root).despawn();
+    commands.queue(|world: &mut World| {
+        move_to_camera_start(world);
+        if let Some(asset_path) = world.resource::<CurrentScene>().path.clone() {
+            restore_history(world, &file_path(&asset_path));
+        }
+    });
+}
+
+/// Reads the history saved next to the scene file at `path`, finding its entities by [`SceneGuid`].
+pub fn restore_history(world: &mut World, path: &Path) {
+    let record = match HistoryRecord::load(path) {
+        Ok(record) => record,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return,
+        Err(error) => {
+            warn!("Failed to load history for {}: {error}", path.display());
+            return;
+        }
+    };
+
+    let by_guid: HashMap<u128, Entity> = world
+        .query::<(Entity, &SceneGuid)>()
+        .iter(world)
+        .map(|(entity, guid)| (guid.0.as_u128(), entity))
+        .collect();
+    let mut entity_map: EntityHashMap<Entity> = record
+        .guids
+        .iter()
+        .filter_map(|(bits, guid)| Some((Entity::try_from_bits(*bits).ok()?, *by_guid.get(guid)?)))
+        .collect();
+    record.map_deleted_entities(world, &mut entity_map);
+    world.resource_mut::<CurrentScene>().generation = record.generation;
+
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    world
+        .resource_mut::<HistoryManager>()
+        .restore(record, &type_registry.read(), &entity_map);
+}
+
+/// What the path prompt opened from the File menu will do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScenePathPrompt {
+    Open,
+    SaveAs,
+    SavePrefab(Entity),
+    InstantiatePrefab,
+}
+
+/// Asks for a scene path. Returns it once confirmed; `prompt` is cleared on confirm or cancel.
+pub fn scene_path_ui(
+    ctx: &egui::Context,
+    prompt: &mut Option<(ScenePathPrompt, String)>,
+) -> Option<(ScenePathPrompt, PathBuf)> {
+    let (kind, path) = prompt.as_mut()?;
+    let kind = *kind;
+    let mut confirmed = false;
+    let mut cancelled = false;
+
+    let title = match kind {
+        ScenePathPrompt::Open => "Open scene",
+        ScenePathPrompt::SaveAs => "Save scene as",
+        ScenePathPrompt::SavePrefab(_) => "Save as prefab",
+        ScenePathPrompt::InstantiatePrefab => "Instantiate prefab",
+    };
+    egui::Window::new(title)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("Path relative to the assets folder:");
+            let response = ui.text_edit_singleline(path);
+            let entered = response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            ui.horizontal(|ui| {
+                let confirm = match kind {
+                    ScenePathPrompt::Open => "Open",
+                    ScenePathPrompt::SaveAs | ScenePathPrompt::SavePrefab(_) => "Save",
+                    ScenePathPrompt::InstantiatePrefab => "Instantiate",
+                };
+                confirmed = ui.button(confirm).clicked() || entered;
+                cancelled = ui.button("Cancel").clicked();
+            });
+        });
+
+    if cancelled {
+        *prompt = None;
+    }
+    if confirmed {
+        let (kind, path) = prompt.take()?;
+        return Some((kind, PathBuf::from(path)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Name, OnAdd};
+    use super::*;
+
+    #[test]
+    fn scene_entities_leave_out_tooling_and_engine_entities() {
+        let mut world = World::new();
+        let content = world.spawn(Name::new("No transform")).id();
+        world.spawn(EditorOnly);
+        world.spawn(Window::default());
+        world.spawn(DynamicSceneRoot::default());
+        world.add_observer(|_: Trigger<OnAdd, Name>| {});
+
+        assert_eq!(scene_entities(&mut world), [content]);
+    }
+}
@@ -0,0 +1,163 @@
+use std::any::TypeId;
+use bevy::log::warn;
+use bevy::prelude::{
+    AppTypeRegistry, Commands, Entity, Name, OnAdd, Query, ReflectComponent, Transform, Trigger,
+    World,
+};
+use bevy::reflect::{GetPath, PartialReflect, ReflectRef};
+use domain::{EntityLink, SceneGuid};
+use crate::editor_commands::{HistoryManager, PatchTarget, ReflectPatch, ReflectPatchCommand};
+use crate::scene_file::SceneContent;
+
+/// Gives every scene entity a [`SceneGuid`] when it's placed in the world.
+/// Entities loaded from a scene keep the one they were saved with.
+pub fn assign_scene_guid(
+    trigger: Trigger<OnAdd, Transform>,
+    content: Query<(), SceneContent>,
+    mut commands: Commands,
+) {
+    let entity = trigger.entity();
+    if !content.contains(entity) {
+        return;
+    }
+    commands.entity(entity).try_insert_if_new(SceneGuid::new());
+}
+
+/// A field holding an [`EntityLink`], found by reflecting over an entity's components.
+struct LinkField {
+    component: &'static str,
+    type_id: TypeId,
+    field: String,
+    link: EntityLink,
+}
+
+fn link_fields(world: &World, entity: Entity) -> Vec<LinkField> {
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+    let mut fields = Vec::new();
+
+    for info in world.inspect_entity(entity) {
+        let Some(registration) = info.type_id().and_then(|type_id| type_registry.get(type_id)) else {
+            continue;
+        };
+        let Some(component) = registration
+            .data::<ReflectComponent>()
+            .and_then(|reflect_component| reflect_component.reflect(world.entity(entity)))
+        else {
+            continue;
+        };
+        let ReflectRef::Struct(value) = component.reflect_ref() else {
+            continue;
+        };
+        for index in 0..value.field_len() {
+            let (Some(name), Some(field)) = (value.name_at(index), value.field_at(index)) else {
+                continue;
+            };
+            if let Some(link) = field.try_downcast_ref::<EntityLink>() {
+                fields.push(LinkField {
+                    component: registration.type_info().type_path_table().short_path(),
+                    type_id: registration.type_id(),
+                    field: name.to_string(),
+                    link: *link,
+                });
+            }
+        }
+    }
+    fields
+}
+
+fn entity_label(world: &World, entity: Entity) -> String {
+    match world.get::<Name>(entity) {
+        Some(name) => format!("{name} ({entity})"),
+        None => entity.to_string(),
+    }
+}
+
+/// Pickers for the entity references of `entity`, choosing among entities with a [`SceneGuid`].
+/// Changes are recorded as undo steps.
+pub fn entity_links_ui(ui: &mut egui::Ui, world: &mut World, entity: Entity) {
+    let fields = link_fields(world, entity);
+    if fields.is_empty() {
+        return;
+    }
+
+    let mut targets: Vec<(Entity, SceneGuid)> = world
+        .query::<(Entity, &SceneGuid)>()
+        .iter(world)
+        .filter(|(target, _)| *target != entity)
+        .map(|(target, guid)| (target, *guid))
+        .collect();
+    targets.sort_by_key(|(target, _)| *target);
+    let labels: Vec<String> = targets
+        .iter()
+        .map(|(target, _)| entity_label(world, *target))
+        .collect();
+
+    let mut picked = None;
+    for field in &fields {
+        ui.horizontal(|ui| {
+            ui.label(format!("{}.{}", field.component, field.field));
+            let selected = field
+                .link
+                .guid
+                .and_then(|guid| targets.iter().position(|(_, target)| target.0 == guid));
+            let text = match (field.link.guid, selected) {
+                (None, _) => "None".to_string(),
+                (Some(_), Some(index)) => labels[index].clone(),
+                (Some(_), None) => "Missing".to_string(),
+            };
+            egui::ComboBox::from_id_salt((entity, field.component, &field.field))
+                .selected_text(text)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(field.link.guid.is_none(), "None").clicked() {
+                        picked = Some((field, EntityLink::default()));
+                    }
+                    for (index, (_, guid)) in targets.iter().enumerate() {
+                        if ui.selectable_label(selected == Some(index), &labels[index]).clicked() {
+                            picked = Some((field, EntityLink::to(*guid)));
+                        }
+                    }
+                });
+        });
+    }
+    ui.separator();
+
+    if let Some((field, link)) = picked {
+        set_link(world, entity, field, link);
+    }
+}
+
+fn set_link(world: &mut World, entity: Entity, field: &LinkField, link: EntityLink) {
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let Some(current) = type_registry
+        .read()
+        .get_type_data::<ReflectComponent>(field.type_id)
+        .and_then(|reflect_component| reflect_component.reflect(world.entity(entity)))
+        .map(|current| current.as_partial_reflect().clone_value())
+    else {
+        return;
+    };
+
+    let mut after = current.clone_value();
+    let applied = after
+        .as_mut()
+        .reflect_path_mut(field.field.as_str())
+        .is_ok_and(|target| target.try_apply(link.as_partial_reflect()).is_ok());
+    if !applied {
+        return;
+    }
+
+    let command = ReflectPatchCommand {
+        label: format!("Set {}.{} on {entity}", field.component, field.field),
+        patches: vec![ReflectPatch::new(
+            PatchTarget::Component(entity, field.type_id),
+            current,
+            after,
+            &type_registry.read(),
+        )],
+    };
+    world.resource_scope::<HistoryManager, _>(|world, mut history| {
+        if let Err(error) = history.execute(Box::new(command), world) {
+            warn!("Failed to set {} on {entity}: {error}", field.field);
+        }
+    });
+}
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+use bevy::asset::io::file::FileAssetReader;
+use bevy::asset::io::AssetSource;
+use bevy::asset::AssetApp;
+use bevy::prelude::{App, World};
+use crate::scene_file::new_scene;
+use crate::UiState;
+
+/// Where File → New looks for the project's own templates, relative to the working directory.
+/// Any scene file put here becomes a template.
+pub const USER_TEMPLATE_DIR: &str = "templates";
+
+/// Asset source that reads [`USER_TEMPLATE_DIR`], see [`register_user_templates`].
+const USER_TEMPLATE_SOURCE: &str = "user_templates";
+
+/// The scene the editor starts with.
+pub const DEFAULT_TEMPLATE: &str = "templates/cornell_box.scn.ron";
+
+/// Templates that ship with the SDK, listed before the project's own.
+const BUILTIN_TEMPLATES: [(&str, &str); 3] = [
+    ("Empty", "templates/empty.scn.ron"),
+    ("Cornell box", DEFAULT_TEMPLATE),
+    ("Outdoor", "templates/outdoor.scn.ron"),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneTemplate {
+    pub name: String,
+    /// Asset path of the template's scene file.
+    pub path: PathBuf,
+}
+
+fn user_template_dir() -> PathBuf {
+    std::env::current_dir().unwrap_or_default().join(USER_TEMPLATE_DIR)
+}
+
+/// Lets the `AssetServer` load the templates in [`USER_TEMPLATE_DIR`], which live outside the
+/// SDK's assets folder. Must be called before the `AssetPlugin` is added.
+pub fn register_user_templates(app: &mut App) {
+    app.register_asset_source(
+        USER_TEMPLATE_SOURCE,
+        AssetSource::build().with_reader(|| Box::new(FileAssetReader::new(user_template_dir()))),
+    );
+}
+
+/// The built-in templates followed by the scenes in [`USER_TEMPLATE_DIR`].
+pub fn scene_templates() -> Vec<SceneTemplate> {
+    let mut templates: Vec<SceneTemplate> = BUILTIN_TEMPLATES
+        .iter()
+        .map(|(name, path)| SceneTemplate {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        })
+        .collect();
+
+    let Ok(entries) = std::fs::read_dir(user_template_dir()) else {
+        return templates;
+    };
+    let mut project: Vec<SceneTemplate> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let name = file_name.strip_suffix(".scn.ron")?.to_string();
+            let path = PathBuf::from(format!("{USER_TEMPLATE_SOURCE}://{file_name}"));
+            Some(SceneTemplate { name, path })
+        })
+        .collect();
+    project.sort_by(|a, b| a.name.cmp(&b.name));
+    templates.extend(project);
+    templates
+}
+
+/// Starts the editor on [`DEFAULT_TEMPLATE`], unless there is unsaved work to recover instead.
+pub fn load_startup_scene(world: &mut World) {
+    if world.resource::<UiState>().recovery.is_none() {
+        new_scene(world, Path::new(DEFAULT_TEMPLATE));
+    }
+}
+
+/// Template picker for File → New. Returns the chosen template's path;
+/// `templates` is cleared on choice or cancel.
+pub fn new_scene_ui(ctx: &egui::Context, templates: &mut Option<Vec<SceneTemplate>>) -> Option<PathBuf> {
+    let list = templates.as_ref()?;
+    let mut chosen = None;
+    let mut cancelled = false;
+
+    egui::Window::new("New scene")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("Template:");
+            for template in list {
+                if ui
+                    .button(&template.name)
+                    .on_hover_text(template.path.display().to_string())
+                    .clicked()
+                {
+                    chosen = Some(template.path.clone());
+                }
+            }
+            ui.separator();
+            cancelled = ui.button("Cancel").clicked();
+        });
+
+    if cancelled || chosen.is_some() {
+        *templates = None;
+    }
+    chosen
+}
@@ -0,0 +1,118 @@
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::{
+    Commands, Entity, Event, EventWriter, Local, Query, Res, ResMut, Resource, With,
+};
+use bevy_inspector_egui::bevy_inspector::hierarchy::SelectedEntities;
+use transform_gizmo_bevy::GizmoTarget;
+use crate::gizmo::GizmoSettings;
+
+/// The selected scene entities. The Hierarchy tab, viewport picking and the gizmo all work
+/// on this resource; the gizmo targets follow it through [`sync_gizmo_targets`].
+///
+/// Clicks replace the selection, Ctrl+click toggles an entity and Shift+click adds it.
+/// In the Hierarchy tab Shift+click selects the range of rows from the last clicked one instead.
+#[derive(Resource, Default)]
+pub struct EditorSelection {
+    entities: SelectedEntities,
+}
+
+impl EditorSelection {
+    /// In the order the entities were selected.
+    pub fn as_slice(&self) -> &[Entity] {
+        self.entities.as_slice()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(entity)
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    pub fn replace(&mut self, entity: Entity) {
+        self.entities.select_replace(entity);
+    }
+
+    pub fn add(&mut self, entity: Entity) {
+        if !self.contains(entity) {
+            self.entities.select_maybe_add(entity, true);
+        }
+    }
+
+    pub fn toggle(&mut self, entity: Entity) {
+        if self.contains(entity) {
+            self.entities.remove(entity);
+        } else {
+            self.entities.select_maybe_add(entity, true);
+        }
+    }
+
+    /// Replaces the selection with `entities`.
+    pub fn set(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        self.entities.clear();
+        for entity in entities {
+            self.add(entity);
+        }
+    }
+
+    /// Swaps ids for the entities they were respawned as, keeping the selection order.
+    pub fn map_entities(&mut self, entity_map: &EntityHashMap<Entity>) {
+        let entities: Vec<Entity> = self
+            .as_slice()
+            .iter()
+            .map(|entity| entity_map.get(entity).copied().unwrap_or(*entity))
+            .collect();
+        self.set(entities);
+    }
+
+    /// For the Hierarchy widget, which edits the selection in place.
+    pub fn selected_entities_mut(&mut self) -> &mut SelectedEntities {
+        &mut self.entities
+    }
+}
+
+/// Sent whenever [`EditorSelection`] changes, with the new selection.
+#[derive(Event, Clone, Debug)]
+pub struct SelectionChanged {
+    pub selected: Vec<Entity>,
+}
+
+/// Drops despawned entities from the selection and puts a `GizmoTarget` on exactly the
+/// entities [`GizmoSettings::targets`] picks from it.
+pub fn sync_gizmo_targets(
+    mut commands: Commands,
+    mut selection: ResMut<EditorSelection>,
+    settings: Res<GizmoSettings>,
+    entities: Query<Entity>,
+    targets: Query<Entity, With<GizmoTarget>>,
+) {
+    if selection.as_slice().iter().any(|entity| !entities.contains(*entity)) {
+        selection.entities.retain(|entity| entities.contains(entity));
+    }
+
+    let selected = settings.targets(&selection);
+    for entity in targets.iter() {
+        if !selected.contains(&entity) {
+            commands.entity(entity).remove::<GizmoTarget>();
+        }
+    }
+    for &entity in selected {
+        if !targets.contains(entity) {
+            commands.entity(entity).insert(GizmoTarget::default());
+        }
+    }
+}
+
+pub fn send_selection_changed(
+    selection: Res<EditorSelection>,
+    mut last: Local<Vec<Entity>>,
+    mut changed: EventWriter<SelectionChanged>,
+) {
+    if selection.as_slice() != last.as_slice() {
+        *last = selection.as_slice().to_vec();
+        changed.send(SelectionChanged {
+            selected: last.clone(),
+        });
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use bevy::app::AppExit;
+use bevy::prelude::{EventReader, EventWriter, Query, Res, ResMut, With, World};
+use bevy_window::{PrimaryWindow, Window, WindowCloseRequested};
+use crate::autosave::{discard_recovery, Autosave};
+use crate::editor_commands::HistoryManager;
+use crate::scene_file::{new_scene, open_scene};
+use crate::UiState;
+
+pub const WINDOW_TITLE: &str = "RRay SDK";
+
+/// Something that would throw away unsaved changes, held back until the user confirms.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PendingAction {
+    Quit,
+    /// Open the scene at this asset path.
+    Open(PathBuf),
+    /// Start a new scene from the template at this asset path.
+    New(PathBuf),
+}
+
+/// Shows an unsaved indicator in the window title.
+pub fn update_window_title(
+    history: Res<HistoryManager>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let title = if history.is_dirty() {
+        format!("{WINDOW_TITLE} *")
+    } else {
+        WINDOW_TITLE.to_string()
+    };
+
+    for mut window in windows.iter_mut() {
+        if window.title != title {
+            window.title = title.clone();
+        }
+    }
+}
+
+/// Quits right away when everything is saved, otherwise asks first.
+pub fn handle_close_requested(
+    mut close_requested: EventReader<WindowCloseRequested>,
+    history: Res<HistoryManager>,
+    mut ui_state: ResMut<UiState>,
+    mut autosave: ResMut<Autosave>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if close_requested.read().last().is_none() {
+        return;
+    }
+
+    if history.is_dirty() {
+        ui_state.pending_action = Some(PendingAction::Quit);
+    } else {
+        discard_recovery(&mut autosave);
+        exit.send(AppExit::Success);
+    }
+}
+
+/// Carries out `action` without asking again.
+pub fn run_pending_action(world: &mut World, action: PendingAction) {
+    match action {
+        PendingAction::Quit => {
+            discard_recovery(&mut world.resource_mut::<Autosave>());
+            world.send_event(AppExit::Success);
+        }
+        PendingAction::Open(path) => open_scene(world, &path),
+        PendingAction::New(template) => new_scene(world, &template),
+    }
+}
+
+/// Modal asking whether to discard unsaved changes. Returns the action once confirmed.
+pub fn unsaved_changes_ui(ctx: &egui::Context, pending: &mut Option<PendingAction>) -> Option<PendingAction> {
+    let action = pending.as_ref()?;
+    let mut confirmed = false;
+    let mut cancelled = false;
+
+    egui::Window::new("Unsaved changes")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("The scene has unsaved changes. Discard them?");
+            ui.horizontal(|ui| {
+                let discard = match action {
+                    PendingAction::Quit => "Discard and quit",
+                    PendingAction::Open(_) => "Discard and open",
+                    PendingAction::New(_) => "Discard and create",
+                };
+                confirmed = ui.button(discard).clicked();
+                cancelled = ui.button("Cancel").clicked();
+            });
+        });
+
+    if cancelled {
+        *pending = None;
+    }
+    if confirmed {
+        return pending.take();
+    }
+    None
+}